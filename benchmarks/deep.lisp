@@ -0,0 +1,16 @@
+; Deep non-tail recursion, with big bodies to evaluate at every level
+(define (depth n)
+  (cond ((= n 0) 0)
+        (else
+         (let ((rest (depth (- n 1))))
+           (when (< rest 0)
+             (println "unreachable" `(n ,n rest ,rest)))
+           (+ 1 rest)))))
+
+(define (repeat times)
+  (let loop ((i 0) (total 0))
+    (if (< i times)
+        (loop (+ i 1) (+ total (depth 5000)))
+        total)))
+
+(println (repeat 100))
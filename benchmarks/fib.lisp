@@ -0,0 +1,7 @@
+; Tree recursion: lots of calls, each with a small body
+(define (fib n)
+  (if (< n 2)
+      n
+      (+ (fib (- n 1)) (fib (- n 2)))))
+
+(println (fib 25))
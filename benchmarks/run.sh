@@ -0,0 +1,13 @@
+#!/bin/sh
+# Times every benchmark with a release build of the interpreter.
+set -e
+cd "$(dirname "$0")/.."
+cargo build --release --quiet
+
+for bench in benchmarks/*.lisp; do
+    start=$(date +%s.%N)
+    ./target/release/crisp "$bench" > /dev/null
+    end=$(date +%s.%N)
+    awk -v name="$bench" -v start="$start" -v end="$end" \
+        'BEGIN { printf "%-24s %.2fs\n", name, end - start }'
+done
@@ -1,37 +1,255 @@
-use crate::lib::interpreter;
+use crate::lib::environment::Environment;
+use crate::lib::error::{Error, ErrorKind};
+use crate::lib::interpreter::{self, Interpreter};
+use crate::lib::span::Span;
+use crate::lib::symbol::Symbol;
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{One, ToPrimitive};
+use std::cell::RefCell;
 use std::fmt;
+use std::iter::FromIterator;
+use std::ops::Deref;
+use std::rc::Rc;
 
+/// A datum as read by the parser, together with the part of the source
+/// it was read from.
 #[derive(Debug, Clone)]
-pub enum DataPre {
+pub struct DataPre {
+    pub kind: DataPreKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum DataPreKind {
     List(Vec<DataPre>),
+    /// A list with a dot before its last datum, like `(a b . c)`.
+    DottedList(Vec<DataPre>, Box<DataPre>),
+    Vector(Vec<DataPre>),
     Int(String),
-    Float(String),
+    Rational(String),
+    Float(f64),
     Str(String),
     Symbol(String),
+    Bool(bool),
+    Char(char),
     Nil,
 }
 
 #[derive(Clone)]
 pub enum Data {
-    List(Vec<Data>),
+    /// A proper list that can't be changed, like the ones read from the
+    /// source code.
+    List(List),
+    /// A cons cell, which lists built at runtime are made of.
+    Pair(Rc<Pair>),
+    /// An array that can be indexed in constant time, changed in place and
+    /// grown. Copies of it share its items.
+    Vector(Rc<RefCell<Vec<Data>>>),
     Int(i64),
+    /// An integer that doesn't fit in an `Int`. Arithmetic functions only
+    /// return these for values outside of the `i64` range.
+    BigInt(BigInt),
+    /// An exact fraction, always in lowest terms and with a denominator
+    /// greater than 1.
+    Rational(BigRational),
     Float(f64),
-    Str(String),
-    Symbol(String),
-    RustFunction(fn(args: &[Data]) -> interpreter::EvalResult),
-    // LispFunction(),
+    Str(Rc<str>),
+    Symbol(Symbol),
+    Bool(bool),
+    Char(char),
+    RustFunction(Rc<NativeFunction>),
+    LispFunction(Rc<Lambda>),
+    /// An error object, as caught by `guard` or made by `error`.
+    Error(Rc<Error>),
     Nil,
 }
 
+/// The items of a `Data::List`, which are shared between copies of the
+/// list. A list can also be the tail of another one, sharing its items
+/// from `start` on. Lists read from the source code remember where they
+/// were read from, so errors in the forms they make up can point at them.
+#[derive(Clone)]
+pub struct List {
+    pub items: Rc<Vec<Data>>,
+    pub start: usize,
+    pub span: Option<Span>,
+}
+
+impl List {
+    /// The list without its first item, which shares the items with this
+    /// one.
+    pub fn rest(&self) -> List {
+        List {
+            items: self.items.clone(),
+            start: (self.start + 1).min(self.items.len()),
+            span: None,
+        }
+    }
+}
+
+impl Deref for List {
+    type Target = [Data];
+
+    fn deref(&self) -> &[Data] {
+        &self.items[self.start..]
+    }
+}
+
+impl From<Vec<Data>> for List {
+    fn from(items: Vec<Data>) -> List {
+        List {
+            items: Rc::new(items),
+            start: 0,
+            span: None,
+        }
+    }
+}
+
+impl FromIterator<Data> for List {
+    fn from_iter<I: IntoIterator<Item = Data>>(iter: I) -> List {
+        List::from(iter.into_iter().collect::<Vec<Data>>())
+    }
+}
+
+/// A cons cell. Its fields can be changed with `set-car!` and `set-cdr!`.
+pub struct Pair {
+    pub car: RefCell<Data>,
+    pub cdr: RefCell<Data>,
+    /// Where the dotted list this pair starts was read from, if it was read
+    /// from source code.
+    pub span: Option<Span>,
+}
+
+impl Drop for Pair {
+    /// Unlinks the rest of the list one pair at a time, since dropping it
+    /// recursively would take one stack frame per pair of a long list.
+    fn drop(&mut self) {
+        let mut rest = self.cdr.replace(Data::Nil);
+        while let Data::Pair(pair) = rest {
+            rest = match Rc::try_unwrap(pair) {
+                Ok(pair) => pair.cdr.replace(Data::Nil),
+                // Still used elsewhere, so not dropped yet
+                Err(_) => break,
+            };
+        }
+    }
+}
+
+/// How many arguments a function accepts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+    Between(usize, usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Arity::Exactly(n) => count == n,
+            Arity::AtLeast(n) => count >= n,
+            Arity::Between(min, max) => min <= count && count <= max,
+        }
+    }
+
+    /// Checks the argument count of a call to the function `name`,
+    /// returning the error to be raised if it's wrong.
+    pub fn check(&self, name: &str, count: usize) -> Result<(), Error> {
+        if self.accepts(count) {
+            Ok(())
+        } else {
+            Err(Error::new(
+                ErrorKind::Arity,
+                format!(
+                    "wrong number of arguments to {}: expected {}, got {}",
+                    name, self, count
+                ),
+            ))
+        }
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Arity::Exactly(1) => write!(f, "1 argument"),
+            Arity::Exactly(n) => write!(f, "{} arguments", n),
+            Arity::AtLeast(1) => write!(f, "at least 1 argument"),
+            Arity::AtLeast(n) => write!(f, "at least {} arguments", n),
+            Arity::Between(min, max) => write!(f, "between {} and {} arguments", min, max),
+        }
+    }
+}
+
+/// The Rust side of a native function. It gets access to the interpreter,
+/// so it can call back into lisp code, and can capture any state it needs.
+pub type NativeFn = dyn Fn(&mut Interpreter, &[Data]) -> interpreter::EvalResult;
+
+/// A function implemented in Rust. It's only called with already
+/// evaluated arguments, and only after their count was checked against
+/// `arity`.
+pub struct NativeFunction {
+    pub name: String,
+    pub arity: Arity,
+    pub function: Box<NativeFn>,
+}
+
+/// A function defined in lisp code, together with the environment it was
+/// defined in.
+pub struct Lambda {
+    /// The name it was defined with, if any, for error messages.
+    pub name: Option<String>,
+    pub params: Vec<Symbol>,
+    pub rest: Option<Symbol>,
+    pub body: Vec<Data>,
+    pub env: Rc<Environment>,
+}
+
+impl Lambda {
+    pub fn name(&self) -> &str {
+        self.name.as_deref().unwrap_or("lambda")
+    }
+
+    pub fn arity(&self) -> Arity {
+        match self.rest {
+            Some(_) => Arity::AtLeast(self.params.len()),
+            None => Arity::Exactly(self.params.len()),
+        }
+    }
+}
+
 impl From<DataPre> for Data {
     fn from(data_pre: DataPre) -> Data {
-        match data_pre {
-            DataPre::Symbol(s) => Data::Symbol(s),
-            DataPre::Str(s) => Data::Str(s),
-            DataPre::Int(i) => Data::Int(i.parse::<i64>().unwrap()),
-            DataPre::Float(f) => Data::Float(f.parse::<f64>().unwrap()),
-            DataPre::List(v) => Data::List(v.iter().map(|pre| Data::from(pre.clone())).collect()),
-            DataPre::Nil => Data::Nil,
+        match data_pre.kind {
+            DataPreKind::Symbol(s) => Data::Symbol(Symbol::intern(&s)),
+            DataPreKind::Str(s) => Data::Str(s.into()),
+            DataPreKind::Bool(b) => Data::Bool(b),
+            DataPreKind::Char(c) => Data::Char(c),
+            DataPreKind::Int(i) => match i.parse::<i64>() {
+                Ok(i) => Data::Int(i),
+                Err(_) => Data::BigInt(i.parse::<BigInt>().unwrap()),
+            },
+            DataPreKind::Rational(r) => Data::from_rational(r.parse::<BigRational>().unwrap()),
+            DataPreKind::Float(f) => Data::Float(f),
+            DataPreKind::List(v) => Data::List(List {
+                items: Rc::new(v.into_iter().map(Data::from).collect()),
+                start: 0,
+                span: Some(data_pre.span),
+            }),
+            DataPreKind::DottedList(v, tail) => {
+                // The parser makes sure there's a datum before the dot
+                let mut items = v.into_iter().map(Data::from);
+                let car = items.next().unwrap();
+                let cdr = Data::list_with_tail(items.collect(), Data::from(*tail));
+                Data::Pair(Rc::new(Pair {
+                    car: RefCell::new(car),
+                    cdr: RefCell::new(cdr),
+                    span: Some(data_pre.span),
+                }))
+            }
+            DataPreKind::Vector(v) => Data::vector(v.into_iter().map(Data::from).collect()),
+            DataPreKind::Nil => Data::Nil,
         }
     }
 }
@@ -45,23 +263,344 @@ impl fmt::Debug for Data {
 impl Data {
     pub fn repr(&self) -> String {
         match self {
-            Data::Symbol(s) => s.clone(),
+            Data::Symbol(s) => s.to_string(),
             Data::Str(s) => format!("{:?}", s),
             Data::Int(i) => format!("{}", i),
-            Data::Float(f) => format!("{}", f),
-            Data::List(v) => format!(
-                "({})",
-                v.iter().map(Data::repr).collect::<Vec<String>>().join(" ")
+            Data::BigInt(i) => format!("{}", i),
+            Data::Rational(r) => format!("{}", r),
+            Data::Float(f) if f.is_nan() => "+nan.0".into(),
+            Data::Float(f) if f.is_infinite() => {
+                format!("{}inf.0", if *f > 0.0 { "+" } else { "-" })
+            }
+            Data::Float(f) => format!("{:?}", f), // Keeps the ".0" on integral floats
+            Data::List(_) | Data::Pair(_) => {
+                let (items, tail) = self.list_parts();
+                match items.as_slice() {
+                    // Quotes are printed back the way they're usually written
+                    [Data::Symbol(name), quoted]
+                        if tail.is_null() && quote_prefix(&name.name()).is_some() =>
+                    {
+                        format!("{}{}", quote_prefix(&name.name()).unwrap(), quoted.repr())
+                    }
+                    _ => {
+                        let mut inner = items.iter().map(Data::repr).collect::<Vec<String>>();
+                        if let Data::Pair(_) = tail {
+                            // A circular list, which goes on like this
+                            inner.push("...".into());
+                        } else if !tail.is_null() {
+                            inner.push(".".into());
+                            inner.push(tail.repr());
+                        }
+                        format!("({})", inner.join(" "))
+                    }
+                }
+            }
+            Data::Vector(v) => format!(
+                "#({})",
+                v.borrow()
+                    .iter()
+                    .map(Data::repr)
+                    .collect::<Vec<String>>()
+                    .join(" ")
             ),
+            Data::Bool(true) => "#t".into(),
+            Data::Bool(false) => "#f".into(),
+            Data::Char(c) => format!("#\\{}", char_name(*c)),
             Data::RustFunction(_) => "#rust/fn".into(),
+            Data::LispFunction(_) => "#lisp/fn".into(),
+            Data::Error(e) => format!(
+                "#<error {}>",
+                std::iter::once(format!("{:?}", e.message))
+                    .chain(e.irritants.iter().map(Data::repr))
+                    .collect::<Vec<String>>()
+                    .join(" ")
+            ),
             Data::Nil => "nil".into(),
         }
     }
 
+    pub fn cons(car: Data, cdr: Data) -> Data {
+        Data::Pair(Rc::new(Pair {
+            car: RefCell::new(car),
+            cdr: RefCell::new(cdr),
+            span: None,
+        }))
+    }
+
+    pub fn vector(items: Vec<Data>) -> Data {
+        Data::Vector(Rc::new(RefCell::new(items)))
+    }
+
+    /// Makes a proper list out of pairs, which (unlike a `Data::List`) can
+    /// be changed.
+    pub fn list(items: Vec<Data>) -> Data {
+        Data::list_with_tail(items, Data::List(Vec::new().into()))
+    }
+
+    /// Makes a chain of pairs with `items` in their cars, and ending in
+    /// `tail`, which is shared (not copied) by the result.
+    pub fn list_with_tail(items: Vec<Data>, tail: Data) -> Data {
+        items
+            .into_iter()
+            .rev()
+            .fold(tail, |cdr, car| Data::cons(car, cdr))
+    }
+
+    /// Whether this is the empty list (or nil, which ends lists too).
+    pub fn is_null(&self) -> bool {
+        match self {
+            Data::Nil => true,
+            Data::List(list) => list.is_empty(),
+            _ => false,
+        }
+    }
+
+    /// The first item of a pair or non-empty list.
+    pub fn car(&self) -> Option<Data> {
+        match self {
+            Data::Pair(pair) => Some(pair.car.borrow().clone()),
+            Data::List(list) => list.first().cloned(),
+            _ => None,
+        }
+    }
+
+    /// What comes after the first item of a pair or non-empty list.
+    pub fn cdr(&self) -> Option<Data> {
+        match self {
+            Data::Pair(pair) => Some(pair.cdr.borrow().clone()),
+            Data::List(list) if !list.is_empty() => Some(Data::List(list.rest())),
+            _ => None,
+        }
+    }
+
+    /// Splits a chain of pairs (which can end in a `Data::List`) into its
+    /// items and what it ends with: the empty list or nil if it's a proper
+    /// list, anything else if it's an improper one. Anything that isn't a
+    /// pair is its own tail. The walk of a circular list stops once it has
+    /// gone around the cycle, with a pair of the cycle as the tail.
+    pub fn list_parts(&self) -> (Vec<Data>, Data) {
+        let mut items = Vec::new();
+        let mut rest = self.clone();
+        // Walks one pair for every two of `rest`, which catches up with it
+        // if the list is circular
+        let mut slow = self.clone();
+        loop {
+            rest = match rest {
+                Data::Pair(pair) => {
+                    items.push(pair.car.borrow().clone());
+                    let cdr = pair.cdr.borrow().clone();
+                    cdr
+                }
+                Data::List(list) if !list.is_empty() => {
+                    items.extend(list.iter().cloned());
+                    return (items, Data::List(Vec::new().into()));
+                }
+                tail => return (items, tail),
+            };
+            if items.len() % 2 == 0 {
+                if let Data::Pair(pair) = slow {
+                    let cdr = pair.cdr.borrow().clone();
+                    slow = cdr;
+                }
+            }
+            if let (Data::Pair(a), Data::Pair(b)) = (&rest, &slow) {
+                if Rc::ptr_eq(a, b) {
+                    return (items, rest);
+                }
+            }
+        }
+    }
+
+    /// The items of a proper list, or `None` if this isn't one.
+    pub fn list_items(&self) -> Option<Vec<Data>> {
+        match self.list_parts() {
+            (items, tail) if tail.is_null() => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Whether this is a proper list, that is, a chain of pairs ending in
+    /// the empty list, as in `list?`. Circular lists aren't.
+    pub fn is_list(&self) -> bool {
+        let next = |data: &Data| match data {
+            Data::Pair(pair) => Some(pair.cdr.borrow().clone()),
+            _ => None,
+        };
+        // The fast cursor walks two pairs for each one the slow cursor
+        // walks, so it catches up with it if the list is circular.
+        let mut slow = self.clone();
+        let mut fast = self.clone();
+        loop {
+            for _ in 0..2 {
+                fast = match fast {
+                    Data::List(_) | Data::Nil => return true,
+                    Data::Pair(_) => next(&fast).unwrap(),
+                    _ => return false,
+                };
+            }
+            slow = next(&slow).unwrap();
+            if let (Data::Pair(a), Data::Pair(b)) = (&slow, &fast) {
+                if Rc::ptr_eq(a, b) {
+                    return false;
+                }
+            }
+        }
+    }
+
+    /// Makes an integer, using a `Data::BigInt` only if it doesn't fit in a
+    /// `Data::Int`.
+    pub fn from_bigint(i: BigInt) -> Data {
+        match i.to_i64() {
+            Some(i) => Data::Int(i),
+            None => Data::BigInt(i),
+        }
+    }
+
+    /// Makes an exact number, which is an integer if `r` has no fractional
+    /// part.
+    pub fn from_rational(r: BigRational) -> Data {
+        if r.denom().is_one() {
+            Data::from_bigint(r.to_integer())
+        } else {
+            Data::Rational(r)
+        }
+    }
+
+    /// Whether this value counts as true in a conditional (`if`, `cond`,
+    /// `and`, `or`...). Only `#f`, nil and the empty list are false;
+    /// everything else, including `0` and `""`, is true.
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Data::Bool(b) => *b,
+            Data::Nil => false,
+            Data::List(list) => !list.is_empty(),
+            _ => true,
+        }
+    }
+
+    /// Whether two values are the same object, as in `eq?`. Symbols,
+    /// booleans, characters, nil and `Int`s are compared by value, and
+    /// strings, pairs, vectors, functions and error objects by identity.
+    /// Lists are compared by identity too, except that all empty lists are
+    /// `eq?`.
+    pub fn is_eq(&self, other: &Data) -> bool {
+        match (self, other) {
+            (Data::Symbol(a), Data::Symbol(b)) => a == b,
+            (Data::Bool(a), Data::Bool(b)) => a == b,
+            (Data::Char(a), Data::Char(b)) => a == b,
+            (Data::Int(a), Data::Int(b)) => a == b,
+            (Data::Nil, Data::Nil) => true,
+            (Data::List(a), Data::List(b)) => {
+                (Rc::ptr_eq(&a.items, &b.items) && a.start == b.start)
+                    || (a.is_empty() && b.is_empty())
+            }
+            (Data::Pair(a), Data::Pair(b)) => Rc::ptr_eq(a, b),
+            (Data::Vector(a), Data::Vector(b)) => Rc::ptr_eq(a, b),
+            (Data::Str(a), Data::Str(b)) => Rc::ptr_eq(a, b),
+            (Data::RustFunction(a), Data::RustFunction(b)) => Rc::ptr_eq(a, b),
+            (Data::LispFunction(a), Data::LispFunction(b)) => Rc::ptr_eq(a, b),
+            (Data::Error(a), Data::Error(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+
+    /// Whether two values look the same, as in `equal?`. Lists, pairs,
+    /// vectors and strings are compared by their contents, and numbers by
+    /// value (although an exact number is never equal to a float).
+    /// Everything else is compared with `is_eq`.
+    pub fn is_equal(&self, other: &Data) -> bool {
+        match (self, other) {
+            (Data::BigInt(a), Data::BigInt(b)) => a == b,
+            (Data::Rational(a), Data::Rational(b)) => a == b,
+            (Data::Float(a), Data::Float(b)) => a == b,
+            (Data::Str(a), Data::Str(b)) => a == b,
+            (Data::Vector(a), Data::Vector(b)) => {
+                let (a, b) = (a.borrow(), b.borrow());
+                a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| a.is_equal(b))
+            }
+            (Data::List(_) | Data::Pair(_), Data::List(_) | Data::Pair(_)) => {
+                let (a_items, a_tail) = self.list_parts();
+                let (b_items, b_tail) = other.list_parts();
+                a_items.len() == b_items.len()
+                    && a_items.iter().zip(&b_items).all(|(a, b)| a.is_equal(b))
+                    && match (&a_tail, &b_tail) {
+                        // The lists are circular, and only equal as the same list
+                        (Data::Pair(_), _) | (_, Data::Pair(_)) => a_tail.is_eq(&b_tail),
+                        _ => (a_tail.is_null() && b_tail.is_null()) || a_tail.is_equal(&b_tail),
+                    }
+            }
+            _ => self.is_eq(other),
+        }
+    }
+
     pub fn to_lisp_string(&self) -> String {
         match self {
-            Data::Str(s) => s.clone(),
+            Data::Str(s) => s.to_string(),
+            Data::Char(c) => c.to_string(),
             _ => self.repr(), // The debug repr is able to handle the rest.
         }
     }
 }
+
+/// The reader shorthand for a quoting form, if `name` is one.
+fn quote_prefix(name: &str) -> Option<&'static str> {
+    match name {
+        "quote" => Some("'"),
+        "quasiquote" => Some("`"),
+        "unquote" => Some(","),
+        "unquote-splicing" => Some(",@"),
+        _ => None,
+    }
+}
+
+/// The names of the characters that have one in the `#\\name` syntax.
+pub const CHAR_NAMES: &[(&str, char)] = &[
+    ("space", ' '),
+    ("newline", '\n'),
+    ("tab", '\t'),
+    ("return", '\r'),
+    ("null", '\0'),
+    ("alarm", '\x07'),
+    ("backspace", '\x08'),
+    ("delete", '\x7f'),
+    ("escape", '\x1b'),
+];
+
+/// How a character is written after the `#\\` in its repr.
+fn char_name(c: char) -> String {
+    match CHAR_NAMES.iter().find(|(_, named)| *named == c) {
+        Some((name, _)) => name.to_string(),
+        None if c.is_control() => format!("x{:x}", c as u32),
+        None => c.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_long_lists() {
+        let list = Data::list((0..5_000_000).map(Data::Int).collect());
+        drop(list);
+    }
+
+    #[test]
+    fn keeps_shared_tails() {
+        let tail = Data::list(vec![Data::Int(2), Data::Int(3)]);
+        drop(Data::cons(Data::Int(1), tail.clone()));
+        assert_eq!(tail.repr(), "(2 3)");
+    }
+
+    #[test]
+    fn walks_circular_lists() {
+        let list = Data::list(vec![Data::Int(1), Data::Int(2)]);
+        if let Data::Pair(second) = list.cdr().unwrap() {
+            *second.cdr.borrow_mut() = list.clone();
+        }
+        assert!(list.list_items().is_none());
+        assert!(!list.is_list());
+        assert!(list.is_equal(&list));
+        assert_eq!(list.repr(), "(1 2 1 ...)");
+    }
+}
@@ -0,0 +1,105 @@
+//! Variable bindings.
+//!
+//! An environment is a frame of bindings linked to the frame of the
+//! enclosing scope, up to the global frame. Frames are shared: closures
+//! keep the environment they were made in alive, and see later definitions
+//! made in it.
+
+use crate::lib::data::Data;
+use crate::lib::symbol::Symbol;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+pub struct Environment {
+    frame: RefCell<Frame>,
+    parent: Option<Rc<Environment>>,
+}
+
+/// The bindings of a frame. Values live in slots that are never removed,
+/// so the address of a binding stays valid once it's been resolved.
+#[derive(Default)]
+struct Frame {
+    slots: HashMap<Symbol, usize>,
+    values: Vec<Data>,
+}
+
+impl Environment {
+    /// Makes a global environment, without any bindings.
+    pub fn new() -> Rc<Environment> {
+        Rc::new(Environment {
+            frame: RefCell::default(),
+            parent: None,
+        })
+    }
+
+    /// Makes an empty frame for a scope nested in `parent`.
+    pub fn extend(parent: &Rc<Environment>) -> Rc<Environment> {
+        Rc::new(Environment {
+            frame: RefCell::default(),
+            parent: Some(parent.clone()),
+        })
+    }
+
+    /// Binds `name` in this frame, replacing its value if it's already
+    /// bound here.
+    pub fn define(&self, name: Symbol, value: Data) {
+        let mut frame = self.frame.borrow_mut();
+        match frame.slots.get(&name) {
+            Some(&index) => frame.values[index] = value,
+            None => {
+                let index = frame.values.len();
+                frame.slots.insert(name, index);
+                frame.values.push(value);
+            }
+        }
+    }
+
+    /// Changes the value of `name` in the innermost frame that binds it.
+    /// Returns false if none does.
+    pub fn set(&self, name: Symbol, value: Data) -> bool {
+        match self.resolve(name) {
+            Some((depth, index)) => {
+                self.ancestor(depth).frame.borrow_mut().values[index] = value;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn lookup(&self, name: Symbol) -> Option<Data> {
+        self.resolve(name)
+            .map(|(depth, index)| self.lookup_at(depth, index))
+    }
+
+    /// Finds where `name` is bound: how many frames up from this one, and
+    /// in which slot of that frame.
+    pub fn resolve(&self, name: Symbol) -> Option<(usize, usize)> {
+        let mut env = self;
+        let mut depth = 0;
+        loop {
+            if let Some(&index) = env.frame.borrow().slots.get(&name) {
+                return Some((depth, index));
+            }
+            env = env.parent.as_deref()?;
+            depth += 1;
+        }
+    }
+
+    /// The value at an address returned by `resolve`, which is faster than
+    /// looking the name up again.
+    pub fn lookup_at(&self, depth: usize, index: usize) -> Data {
+        self.ancestor(depth).frame.borrow().values[index].clone()
+    }
+
+    fn ancestor(&self, depth: usize) -> &Environment {
+        let mut env = self;
+        for _ in 0..depth {
+            env = env
+                .parent
+                .as_deref()
+                .expect("address deeper than the environment");
+        }
+        env
+    }
+}
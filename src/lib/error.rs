@@ -0,0 +1,142 @@
+//! The errors raised while reading or evaluating code.
+
+use crate::lib::data::Data;
+use crate::lib::span::Span;
+use std::fmt;
+use std::rc::Rc;
+
+/// What kind of problem an error is about, for host code that wants to
+/// handle some of them differently.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    /// The source code couldn't be parsed.
+    Parse,
+    /// A special form was written with the wrong shape, like `(if)`.
+    Syntax,
+    UnboundVariable,
+    /// A value of the wrong type was used, like calling a number.
+    Type,
+    /// A function was called with the wrong number of arguments.
+    Arity,
+    /// A value of the right type that still can't be used, like a zero
+    /// divisor.
+    Value,
+    /// An error raised by lisp code.
+    User,
+    /// Evaluations nested deeper than the interpreter allows.
+    RecursionLimit,
+    Io,
+}
+
+/// How many calls of an error's call stack `Error::render` shows.
+const MAX_RENDERED_FRAMES: usize = 10;
+
+/// A call to a lisp function that an error unwound through.
+#[derive(Debug, Clone)]
+pub struct StackFrame {
+    pub function: String,
+    /// Where the function was called from, if that was read from source
+    /// code.
+    pub call_site: Option<Span>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Error {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// The values the error is about, shown after the message.
+    pub irritants: Vec<Data>,
+    /// The object passed to `raise`, if the error was raised that way.
+    pub payload: Option<Box<Data>>,
+    /// The innermost form read from source code the error happened in.
+    pub span: Option<Span>,
+    /// The lisp function calls the error went through, innermost first.
+    /// Tail calls replace the call they're made from, so only the last one
+    /// of a chain of them is here.
+    pub call_stack: Vec<StackFrame>,
+    /// Whether the exception handlers installed where the error was raised
+    /// were already called with it, so they aren't called again as it
+    /// unwinds.
+    pub handlers_called: bool,
+}
+
+impl Error {
+    pub fn new<S: Into<String>>(kind: ErrorKind, message: S) -> Error {
+        Error {
+            kind,
+            message: message.into(),
+            irritants: Vec::new(),
+            payload: None,
+            span: None,
+            call_stack: Vec::new(),
+            handlers_called: false,
+        }
+    }
+
+    pub fn with_irritants(mut self, irritants: Vec<Data>) -> Error {
+        self.irritants = irritants;
+        self
+    }
+
+    /// The error raised by `(raise object)`. Raising an error object raises
+    /// the error it came from again.
+    pub fn raised(object: Data) -> Error {
+        match object {
+            Data::Error(error) => Error {
+                handlers_called: false,
+                ..(*error).clone()
+            },
+            object => {
+                let mut error = Error::new(
+                    ErrorKind::User,
+                    format!("uncaught exception: {}", object.repr()),
+                );
+                error.payload = Some(Box::new(object));
+                error
+            }
+        }
+    }
+
+    /// The value exception handlers get for this error: the object passed
+    /// to `raise`, or an error object otherwise.
+    pub fn to_data(&self) -> Data {
+        match &self.payload {
+            Some(payload) => (**payload).clone(),
+            None => Data::Error(Rc::new(self.clone())),
+        }
+    }
+
+    /// Formats the error for the user: the message, the source line it
+    /// happened in and the calls it went through.
+    pub fn render(&self) -> String {
+        let mut rendered = match &self.span {
+            Some(span) => span.render(&self.to_string()),
+            None => format!("error: {}", self),
+        };
+        for frame in self.call_stack.iter().take(MAX_RENDERED_FRAMES) {
+            rendered.push_str(&format!("\n  = in {}", frame.function));
+            if let Some(span) = &frame.call_site {
+                rendered.push_str(&format!(", called at {:?}", span));
+            }
+        }
+        if self.call_stack.len() > MAX_RENDERED_FRAMES {
+            rendered.push_str(&format!(
+                "\n  = ... and {} more calls",
+                self.call_stack.len() - MAX_RENDERED_FRAMES
+            ));
+        }
+        rendered
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        for irritant in &self.irritants {
+            write!(f, " {}", irritant.repr())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for Error {}
@@ -1,128 +1,1117 @@
-use crate::lib::data::Data;
-use std::collections::HashMap;
+use crate::lib::data::{Arity, Data, DataPre, Lambda, List, NativeFunction};
+use crate::lib::environment::Environment;
+use crate::lib::error::{Error, ErrorKind, StackFrame};
+use crate::lib::span::Span;
+use crate::lib::stdlib;
+use crate::lib::symbol::Symbol;
+use std::borrow::Cow;
+use std::rc::Rc;
 
-type Scope<'a> = HashMap<&'a str, Data>;
-pub type EvalResult = Result<Data, String>; // A method for raising exceptions. I should make something better later.
+/// The (unevaluated) bindings of a `let`-like form.
+type Bindings = Vec<(Symbol, Data)>;
+pub type EvalResult = Result<Data, Error>;
 
-// TODO: Make a way to choose between REPL & Run File (also, do command-line parsing)
-pub struct Interpreter<'a> {
-    scopes: Vec<Scope<'a>>,
-    program: Vec<Data>,
+/// How deeply evaluations can nest by default, see
+/// `Interpreter::set_max_depth`.
+pub const DEFAULT_MAX_DEPTH: usize = 10_000;
+
+/// What evaluating a form leads to. Forms hand back the expression in
+/// their tail position (or the function they tail call) instead of
+/// evaluating it themselves, so tail calls don't grow the Rust stack.
+enum Tail {
+    Done(Data),
+    /// Evaluate this expression, in the environment the form left.
+    Eval(Data),
+    /// Call this function with these arguments (already checked against
+    /// its arity), called from the given place.
+    Call(Rc<Lambda>, Vec<Data>, Option<Span>),
+}
+
+/// The forms whose operands aren't evaluated like function arguments.
+#[derive(Clone, Copy)]
+enum SpecialForm {
+    Quote,
+    Quasiquote,
+    Unquote,
+    Lambda,
+    Define,
+    Set,
+    Let,
+    LetStar,
+    Letrec,
+    Begin,
+    If,
+    When,
+    Unless,
+    Cond,
+    Guard,
+    Try,
+    And,
+    Or,
+}
+
+/// The symbols that mean something to the evaluator, interned once so
+/// that forms are recognized by symbol id instead of by name.
+struct Keywords {
+    /// The special form each symbol names, if any, indexed by symbol id.
+    special_forms: Vec<Option<SpecialForm>>,
+    quasiquote: Symbol,
+    unquote: Symbol,
+    unquote_splicing: Symbol,
+    else_: Symbol,
+    rest: Symbol,
+    catch: Symbol,
+    finally: Symbol,
+}
+
+impl Keywords {
+    fn new() -> Keywords {
+        use SpecialForm::*;
+        let special_forms = [
+            ("quote", Quote),
+            ("quasiquote", Quasiquote),
+            ("unquote", Unquote),
+            ("unquote-splicing", Unquote),
+            ("lambda", Lambda),
+            ("fn", Lambda),
+            ("define", Define),
+            ("set!", Set),
+            ("let", Let),
+            ("let*", LetStar),
+            ("letrec", Letrec),
+            ("begin", Begin),
+            ("if", If),
+            ("when", When),
+            ("unless", Unless),
+            ("cond", Cond),
+            ("guard", Guard),
+            ("try", Try),
+            ("and", And),
+            ("or", Or),
+        ];
+        let mut table = Vec::new();
+        for &(name, form) in special_forms.iter() {
+            let id = Symbol::intern(name).id();
+            if table.len() <= id {
+                table.resize(id + 1, None);
+            }
+            table[id] = Some(form);
+        }
+        Keywords {
+            special_forms: table,
+            quasiquote: Symbol::intern("quasiquote"),
+            unquote: Symbol::intern("unquote"),
+            unquote_splicing: Symbol::intern("unquote-splicing"),
+            else_: Symbol::intern("else"),
+            rest: Symbol::intern("&rest"),
+            catch: Symbol::intern("catch"),
+            finally: Symbol::intern("finally"),
+        }
+    }
+}
+
+pub struct Interpreter {
+    global: Rc<Environment>,
+    /// The environment of the code being evaluated.
+    env: Rc<Environment>,
+    program: Rc<[DataPre]>,
+    /// The handlers installed by `with-exception-handler`, innermost last.
+    /// `None` stands for a `guard` or `try` form, which catches what's
+    /// raised in its body itself.
+    handlers: Vec<Option<Data>>,
+    keywords: Rc<Keywords>,
+    /// How many evaluations are currently nested.
+    depth: usize,
+    max_depth: usize,
 }
 
-impl<'a> Interpreter<'a> {
-    pub fn new(data: Vec<Data>) -> Interpreter<'a> {
+impl Interpreter {
+    pub fn new(program: Vec<DataPre>) -> Interpreter {
+        let global = Environment::new();
         let mut interpreter = Interpreter {
-            scopes: Vec::new(),
-            program: data,
+            env: global.clone(),
+            global,
+            program: program.into(),
+            handlers: Vec::new(),
+            keywords: Rc::new(Keywords::new()),
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
         };
-        interpreter
-            .scopes
-            .push(Interpreter::make_standard_library());
+        stdlib::load(&mut interpreter);
 
         interpreter
     }
 
-    fn make_standard_library() -> Scope<'a> {
-        let mut standard: Scope = HashMap::new();
+    /// Binds `name` to `value` in the global scope, overwriting any
+    /// previous definition.
+    pub fn define(&mut self, name: &str, value: Data) {
+        self.global.define(Symbol::intern(name), value);
+    }
 
-        standard.insert("print", Data::RustFunction(|args| {
-            let mut output_base = Vec::<String>::new();
-            for arg in args {
-                output_base.push(arg.to_lisp_string());
+    /// Binds `name` to a function implemented in Rust in the global scope.
+    /// The function can capture state from the host program and use the
+    /// interpreter it's called from, e.g. to call functions it was passed.
+    pub fn define_native<F>(&mut self, name: &str, arity: Arity, function: F)
+    where
+        F: Fn(&mut Interpreter, &[Data]) -> EvalResult + 'static,
+    {
+        let native = NativeFunction {
+            name: name.into(),
+            arity,
+            function: Box::new(function),
+        };
+        self.define(name, Data::RustFunction(Rc::new(native)));
+    }
+
+    /// Sets how deeply evaluations can nest (roughly, how deep non-tail
+    /// recursion can go) before a recursion limit error is raised. Tail
+    /// calls don't count. The Rust stack has to be big enough for it.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    pub fn start(&mut self) -> i32 {
+        for form in self.program.clone().iter() {
+            if let Err(e) = self.eval_form(form.clone()) {
+                eprintln!("{}", e.render());
+                return 1;
             }
-            print!("{}", output_base.join(" "));
-            Ok(Data::Nil)
-        }));
+        }
+        0
+    }
 
-        standard.insert("println", Data::RustFunction(|args| {
-            let mut output_base = Vec::<String>::new();
-            for arg in args {
-                output_base.push(arg.to_lisp_string());
+    /// Evaluates a top-level form read from source code. Errors that don't
+    /// point at a more precise place, like unbound variables, point at the
+    /// form.
+    pub fn eval_form(&mut self, form: DataPre) -> EvalResult {
+        let span = form.span.clone();
+        self.eval(Ok(Data::from(form))).map_err(|mut e| {
+            if e.span.is_none() {
+                e.span = Some(span);
             }
-            println!("{}", output_base.join(" "));
-            Ok(Data::Nil)
-        })); // TODO: find a better way to stop repeating code here
+            e
+        })
+    }
+
+    pub fn eval(&mut self, data: EvalResult) -> EvalResult {
+        match data? {
+            list @ Data::List(_) => self.run(Tail::Eval(list)),
+            pair @ Data::Pair(_) => self.run(Tail::Eval(pair)),
+            atom => match self.eval_atom(atom) {
+                Err(error) => Err(self.raise_new(error)),
+                value => value,
+            },
+        }
+    }
 
-        standard.insert("+", Data::RustFunction(|args| {
-            let mut current = Data::Int(0);
-            for arg in args {
-                match arg {
-                    Data::Int(a) => match current {
-                        Data::Float(x) => current = Data::Float(x + (*a as f64)),
-                        Data::Int(x) => current = Data::Int(x + a),
-                        _ => unreachable!(),
-                    },
-                    Data::Float(a) => match current {
-                        Data::Float(x) => current = Data::Float(x + a),
-                        Data::Int(x) => current = Data::Float((x as f64) + a),
-                        _ => unreachable!(),
-                    },
-                    _ => return Err(format!("attempted to use {:?} in function + (wrong argument type)", arg)),
+    fn eval_atom(&self, data: Data) -> EvalResult {
+        match data {
+            Data::Symbol(symbol) => match self.env.lookup(symbol) {
+                // Try to look up variable
+                Some(thing) => Ok(thing),
+                None => Err(Error::new(
+                    ErrorKind::UnboundVariable,
+                    format!(r#"Could not find variable "{}""#, symbol),
+                )),
+            },
+            // A vector literal is read once, but each evaluation of it gets
+            // a new vector, so mutating one doesn't change the literal
+            Data::Vector(items) => Ok(Data::vector(items.borrow().clone())),
+            any => Ok(any),
+        }
+    }
+
+    /// Evaluates `tail`, and then whatever it leads to until there's a
+    /// value. The forms evaluated along the way can leave new frames in
+    /// the environment for the expressions they lead to, so the
+    /// environment is restored at the end. Errors record the last lisp
+    /// function entered in their call stack.
+    fn run(&mut self, mut tail: Tail) -> EvalResult {
+        if self.depth >= self.max_depth {
+            return Err(Error::new(
+                ErrorKind::RecursionLimit,
+                format!("maximum recursion depth exceeded ({})", self.max_depth),
+            ));
+        }
+        self.depth += 1;
+        let caller_env = self.env.clone();
+
+        let mut frame = None;
+        let result = loop {
+            let next = match tail {
+                Tail::Done(value) => break Ok(value),
+                Tail::Eval(Data::List(list)) => self.eval_list(&list).map_err(|mut e| {
+                    if e.span.is_none() {
+                        e.span = list.span.clone();
+                    }
+                    e
+                }),
+                // Code built at runtime, as in (eval (list '+ 1 2))
+                Tail::Eval(Data::Pair(pair)) => {
+                    let form = Data::Pair(pair.clone());
+                    match form.list_items() {
+                        Some(items) => Ok(Tail::Eval(Data::List(List {
+                            span: pair.span.clone(),
+                            ..items.into()
+                        }))),
+                        None => {
+                            let mut error = Error::new(
+                                ErrorKind::Syntax,
+                                format!("cannot evaluate an improper list: {}", form.repr()),
+                            );
+                            error.span = pair.span.clone();
+                            Err(error)
+                        }
+                    }
+                }
+                Tail::Eval(atom) => self.eval_atom(atom).map(Tail::Done),
+                Tail::Call(lambda, args, call_site) => {
+                    frame = Some(StackFrame {
+                        function: lambda.name().into(),
+                        call_site,
+                    });
+                    self.enter_lambda(&lambda, &args);
+                    self.eval_body_tail(&lambda.body)
+                }
+            };
+            match next {
+                Ok(next) => tail = next,
+                Err(mut e) => {
+                    e.call_stack.extend(frame);
+                    break Err(self.raise_new(e));
                 }
             }
+        };
+
+        self.env = caller_env;
+        self.depth -= 1;
+        result
+    }
 
-            Ok(current)
-        }));
+    /// Evaluates an unquoted list, A.K.A. a function call or special form.
+    fn eval_list(&mut self, list: &List) -> Result<Tail, Error> {
+        if list.is_empty() {
+            return Ok(Tail::Done(Data::Nil));
+        }
 
-        standard
+        if let Data::Symbol(name) = &list[0] {
+            if let Some(&Some(form)) = self.keywords.special_forms.get(name.id()) {
+                return self.eval_special_form(form, *name, &list[1..]);
+            }
+        }
+
+        let function = self.eval(Ok(list[0].clone()))?;
+        let mut args = Vec::with_capacity(list.len() - 1);
+        for item in &list[1..] {
+            args.push(self.eval(Ok(item.clone()))?);
+        }
+
+        match function {
+            Data::LispFunction(lambda) => {
+                lambda.arity().check(lambda.name(), args.len())?;
+                Ok(Tail::Call(lambda, args, list.span.clone()))
+            }
+            function => self.apply(&function, &args).map(Tail::Done),
+        }
     }
 
-    pub fn start(&mut self) -> i32 {
-        for data in self.program.clone() {
-            if let Err(e) = self.eval(Ok(data)) {
-                /* Terrible debug here, huh? */
-                println!("An error ocurred: {}", e);
-                return 1;
+    /// Calls `function` with already evaluated arguments, after checking
+    /// that it accepts that many of them.
+    pub fn apply(&mut self, function: &Data, args: &[Data]) -> EvalResult {
+        match function {
+            Data::RustFunction(native) => {
+                native.arity.check(&native.name, args.len())?;
+                (native.function)(self, args)
+            }
+            Data::LispFunction(lambda) => {
+                lambda.arity().check(lambda.name(), args.len())?;
+                self.run(Tail::Call(lambda.clone(), args.to_vec(), None))
             }
+            x => Err(Error::new(
+                ErrorKind::Type,
+                format!("Is not a function: {}", x.repr()),
+            )),
         }
-        0
     }
 
-    pub fn eval(&mut self, data: EvalResult) -> EvalResult {
-        match data? {
-            Data::List(list) => {
-                // Unquoted list, A.K.A. function call
-                if list.len() > 0 {
-                    let mut evaluated_list = list.clone();
-                    for item in evaluated_list.iter_mut() {
-                        *item = match item {
-                            Data::List(list) => self.eval(Ok(item.clone())), // Function recursion
-                            Data::Symbol(symbol) => match self.scope_lookup(&symbol) {
-                                // Try to look up variable
-                                Some(thing) => Ok(thing),
-                                None => Err(format!(r#"Could not find variable "{}""#, symbol)),
-                            },
-                            _ => Ok(item.clone()),
-                        }?;
-                    }
+    /// Calls `thunk` with `handler` installed as the current exception
+    /// handler. Errors reach the handler where they happen, except for
+    /// recursion limit errors (and errors of a native thunk), which are
+    /// raised to it when they get out of the thunk.
+    pub fn with_exception_handler(&mut self, handler: &Data, thunk: &Data) -> EvalResult {
+        self.handlers.push(Some(handler.clone()));
+        let result = match self.apply(thunk, &[]) {
+            Err(error) if !error.handlers_called => Err(self.raise(error)),
+            result => result,
+        };
+        self.handlers.pop();
+        result
+    }
 
-                    match &evaluated_list[0] {
-                        // Panic-safe because it's been asserted before that the list length is greater than 0
-                        Data::RustFunction(f) => {
-                            if list.len() == 1 {
-                                f(&[])
-                            } else {
-                                f(&list[1..])
-                            }
-                        },
-                        x => Err(format!("Is not a function: {}", x.repr())),
+    /// Calls the current exception handler with the error's value, with the
+    /// outer handlers installed. Since execution can't resume where the
+    /// error was raised, the handler returning is an error itself, which is
+    /// raised to the outer handlers. Returns the error to unwind with.
+    pub fn raise(&mut self, mut error: Error) -> Error {
+        let handler = match self.handlers.last() {
+            Some(Some(handler)) => handler.clone(),
+            _ => {
+                error.handlers_called = true;
+                return error;
+            }
+        };
+        self.handlers.pop();
+        let value = error.to_data();
+        let raised = match self.apply(&handler, std::slice::from_ref(&value)) {
+            Ok(_) => {
+                let mut returned = Error::new(
+                    ErrorKind::User,
+                    "exception handler returned from a non-continuable exception:",
+                )
+                .with_irritants(vec![value]);
+                returned.span = error.span;
+                self.raise(returned)
+            }
+            Err(error) if error.handlers_called => error,
+            Err(error) => self.raise(error),
+        };
+        self.handlers.push(Some(handler));
+        raised
+    }
+
+    /// Raises an error that evaluating something failed with, if it wasn't
+    /// raised yet, so the handlers get it before any `finally` clause is
+    /// evaluated on the way out. Recursion limit errors are left for
+    /// `with_exception_handler`, since the handler would hit the limit too.
+    fn raise_new(&mut self, error: Error) -> Error {
+        if error.handlers_called || error.kind == ErrorKind::RecursionLimit {
+            error
+        } else {
+            self.raise(error)
+        }
+    }
+
+    /// Calls the current exception handler with `object` and returns what it
+    /// returns. The handler is called with the outer handlers installed, so
+    /// it can raise the object further. Without handlers, it's raised as an
+    /// error.
+    pub fn raise_continuable(&mut self, object: Data) -> EvalResult {
+        let handler = match self.handlers.last() {
+            Some(Some(handler)) => handler.clone(),
+            _ => return Err(self.raise(Error::raised(object))),
+        };
+        self.handlers.pop();
+        let result = match self.apply(&handler, &[object]) {
+            Err(error) if !error.handlers_called => Err(self.raise(error)),
+            result => result,
+        };
+        self.handlers.push(Some(handler));
+        result
+    }
+
+    /// Evaluates `body` for a `guard` or `try` form, which catches the
+    /// errors raised in it before any outer exception handler does.
+    fn eval_catching(&mut self, body: &[Data]) -> EvalResult {
+        self.handlers.push(None);
+        let result = self.eval_body(body);
+        self.handlers.pop();
+        result
+    }
+
+    /// Evaluates a special form, that is, a form whose operands are passed
+    /// unevaluated instead of being evaluated like function arguments.
+    /// `name` is the symbol it was called by.
+    fn eval_special_form(
+        &mut self,
+        form: SpecialForm,
+        name: Symbol,
+        operands: &[Data],
+    ) -> Result<Tail, Error> {
+        match form {
+            SpecialForm::Quote => match operands {
+                [data] => Ok(Tail::Done(data.clone())),
+                _ => Err(syntax_error("quote", "expected exactly one operand")),
+            },
+            SpecialForm::Quasiquote => match operands {
+                [template] => self.eval_quasiquote(template, 1).map(Tail::Done),
+                _ => Err(syntax_error("quasiquote", "expected exactly one operand")),
+            },
+            SpecialForm::Unquote => Err(syntax_error(&name.name(), "not inside a quasiquote")),
+            SpecialForm::Lambda => self.make_lambda(None, operands).map(Tail::Done),
+            SpecialForm::Define => self.eval_define(operands).map(Tail::Done),
+            SpecialForm::Set => self.eval_set(operands).map(Tail::Done),
+            SpecialForm::Let => self.eval_let(operands),
+            SpecialForm::LetStar => self.eval_let_star(operands),
+            SpecialForm::Letrec => self.eval_letrec(operands),
+            SpecialForm::Begin => self.eval_body_tail(operands),
+            SpecialForm::If => match operands {
+                [test, then] => self.eval_if(test, then, &Data::Nil),
+                [test, then, otherwise] => self.eval_if(test, then, otherwise),
+                _ => Err(syntax_error(
+                    "if",
+                    "expected a test, a consequent and an optional alternative",
+                )),
+            },
+            SpecialForm::When => self.eval_when(true, operands),
+            SpecialForm::Unless => self.eval_when(false, operands),
+            SpecialForm::Cond => self
+                .eval_cond(operands)
+                .map(|tail| tail.unwrap_or(Tail::Done(Data::Nil))),
+            SpecialForm::Guard => self.eval_guard(operands),
+            SpecialForm::Try => self.eval_try(operands).map(Tail::Done),
+            SpecialForm::And => self.eval_and(operands),
+            SpecialForm::Or => self.eval_or(operands),
+        }
+    }
+
+    /// Builds the value of a quasiquote template, evaluating the parts in
+    /// `unquote` and splicing the lists from `unquote-splicing` in. `depth`
+    /// is the number of nested quasiquotes, and only the unquotes at depth
+    /// 1 are evaluated: the ones inside inner quasiquotes are kept for when
+    /// those are evaluated.
+    fn eval_quasiquote(&mut self, template: &Data, depth: usize) -> EvalResult {
+        let keywords = self.keywords.clone();
+        let (items, tail) = match template {
+            Data::List(_) | Data::Pair(_) if !template.is_null() => template.list_parts(),
+            _ => return Ok(template.clone()),
+        };
+        if let Data::Pair(_) = tail {
+            return Err(syntax_error("quasiquote", "circular template"));
+        }
+
+        if tail.is_null() {
+            match &items[..] {
+                [Data::Symbol(name), operand] if *name == keywords.unquote => {
+                    return if depth == 1 {
+                        self.eval(Ok(operand.clone()))
+                    } else {
+                        let operand = self.eval_quasiquote(operand, depth - 1)?;
+                        Ok(Data::list(vec![items[0].clone(), operand]))
+                    };
+                }
+                [Data::Symbol(name), operand] if *name == keywords.quasiquote => {
+                    let operand = self.eval_quasiquote(operand, depth + 1)?;
+                    return Ok(Data::list(vec![items[0].clone(), operand]));
+                }
+                _ => (),
+            }
+        }
+
+        let mut result = Vec::with_capacity(items.len());
+        let mut tail = self.eval_quasiquote(&tail, depth)?;
+        for (i, item) in items.iter().enumerate() {
+            // (a . ,x) is read as (a unquote x), so the unquote is the tail
+            if let (Data::Symbol(name), [_]) = (item, &items[i + 1..]) {
+                if tail.is_null() && (*name == keywords.unquote || *name == keywords.quasiquote) {
+                    tail = self.eval_quasiquote(&Data::list(items[i..].to_vec()), depth)?;
+                    break;
+                }
+            }
+
+            let spliced = match form_items(item).as_deref() {
+                Some([Data::Symbol(name), operand]) if *name == keywords.unquote_splicing => {
+                    Some(operand.clone())
+                }
+                _ => None,
+            };
+
+            match spliced {
+                Some(operand) if depth == 1 => {
+                    let value = self.eval(Ok(operand))?;
+                    match value.list_items() {
+                        Some(items) => result.extend(items),
+                        None => {
+                            return Err(Error::new(
+                                ErrorKind::Type,
+                                format!("unquote-splicing: expected a list, got {}", value.repr()),
+                            ))
+                        }
                     }
+                }
+                Some(operand) => {
+                    let operand = self.eval_quasiquote(&operand, depth - 1)?;
+                    result.push(Data::list(vec![
+                        Data::Symbol(keywords.unquote_splicing),
+                        operand,
+                    ]));
+                }
+                None => result.push(self.eval_quasiquote(item, depth)?),
+            }
+        }
+
+        Ok(Data::list_with_tail(result, tail))
+    }
+
+    /// Evaluates a sequence of expressions, returning the value of the last
+    /// one (or nil if there are none).
+    fn eval_body(&mut self, body: &[Data]) -> EvalResult {
+        let mut result = Data::Nil;
+        for data in body {
+            result = self.eval(Ok(data.clone()))?;
+        }
+        Ok(result)
+    }
+
+    /// Evaluates all but the last expression of a sequence, which is
+    /// returned to be evaluated in tail position.
+    fn eval_body_tail(&mut self, body: &[Data]) -> Result<Tail, Error> {
+        match body.split_last() {
+            Some((last, init)) => {
+                self.eval_body(init)?;
+                Ok(Tail::Eval(last.clone()))
+            }
+            None => Ok(Tail::Done(Data::Nil)),
+        }
+    }
+
+    /// Evaluates `body` in `env`, going back to the current environment
+    /// afterwards, even if the evaluation failed.
+    fn eval_in(&mut self, env: Rc<Environment>, body: &[Data]) -> EvalResult {
+        let outer = std::mem::replace(&mut self.env, env);
+        let result = self.eval_body(body);
+        self.env = outer;
+        result
+    }
+
+    /// Makes a new frame on top of the current environment.
+    fn new_frame(&self) -> Rc<Environment> {
+        Environment::extend(&self.env)
+    }
+
+    fn eval_if(&mut self, test: &Data, then: &Data, otherwise: &Data) -> Result<Tail, Error> {
+        if self.eval(Ok(test.clone()))?.is_truthy() {
+            Ok(Tail::Eval(then.clone()))
+        } else {
+            Ok(Tail::Eval(otherwise.clone()))
+        }
+    }
+
+    /// `(when test body...)`, or `(unless test body...)` if `expected` is
+    /// false.
+    fn eval_when(&mut self, expected: bool, operands: &[Data]) -> Result<Tail, Error> {
+        match operands.split_first() {
+            Some((test, body)) => {
+                if self.eval(Ok(test.clone()))?.is_truthy() == expected {
+                    self.eval_body_tail(body)
                 } else {
-                    Ok(Data::Nil)
+                    Ok(Tail::Done(Data::Nil))
                 }
             }
-            any => Ok(any),
+            None => Err(syntax_error(
+                if expected { "when" } else { "unless" },
+                "missing test",
+            )),
         }
     }
 
-    fn scope_lookup(&self, var_name: &'a str) -> Option<Data> {
-        for (_scope_index, scope) in self.scopes.iter().enumerate().rev() {
-            if let Some(data) = scope.get(var_name) {
-                return Some(data.clone());
+    /// Returns the first false operand without evaluating the ones after
+    /// it, or the last operand if all of them are true.
+    fn eval_and(&mut self, operands: &[Data]) -> Result<Tail, Error> {
+        let (last, init) = match operands.split_last() {
+            Some(split) => split,
+            None => return Ok(Tail::Done(Data::Bool(true))),
+        };
+        for operand in init {
+            let value = self.eval(Ok(operand.clone()))?;
+            if !value.is_truthy() {
+                return Ok(Tail::Done(value));
             }
         }
+        Ok(Tail::Eval(last.clone()))
+    }
+
+    /// Returns the first true operand without evaluating the ones after
+    /// it, or #f if none of them are true.
+    fn eval_or(&mut self, operands: &[Data]) -> Result<Tail, Error> {
+        let (last, init) = match operands.split_last() {
+            Some(split) => split,
+            None => return Ok(Tail::Done(Data::Bool(false))),
+        };
+        for operand in init {
+            let value = self.eval(Ok(operand.clone()))?;
+            if value.is_truthy() {
+                return Ok(Tail::Done(value));
+            }
+        }
+        Ok(Tail::Eval(last.clone()))
+    }
+
+    /// `(cond (test body...)... (else body...))`. A clause without a body
+    /// returns the value of its test. Returns `None` if no clause matched.
+    fn eval_cond(&mut self, clauses: &[Data]) -> Result<Option<Tail>, Error> {
+        for clause in clauses {
+            let clause_items = form_items(clause).unwrap_or_default();
+            let (test, body) = match clause_items.split_first() {
+                Some(split) => split,
+                None => {
+                    return Err(syntax_error(
+                        "cond",
+                        &format!("invalid clause: {}", clause.repr()),
+                    ))
+                }
+            };
+
+            let value = match test {
+                Data::Symbol(s) if *s == self.keywords.else_ => Data::Bool(true),
+                _ => self.eval(Ok(test.clone()))?,
+            };
+            if value.is_truthy() {
+                return if body.is_empty() {
+                    Ok(Some(Tail::Done(value)))
+                } else {
+                    self.eval_body_tail(body).map(Some)
+                };
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// `(guard (name clause...) body...)` evaluates the body, and if it
+    /// raises an error, evaluates the `cond`-like clauses with the error
+    /// bound to `name`. The error is raised again if no clause matches.
+    fn eval_guard(&mut self, operands: &[Data]) -> Result<Tail, Error> {
+        let (spec, body) = match operands.split_first() {
+            Some((spec, body)) => (form_items(spec), body),
+            None => (None, operands),
+        };
+        let (name, clauses) = match spec.as_deref().map(<[Data]>::split_first) {
+            Some(Some((Data::Symbol(name), clauses))) => (*name, clauses),
+            Some(_) => return Err(syntax_error("guard", "expected a variable name")),
+            None => return Err(syntax_error("guard", "expected (name clause...)")),
+        };
+
+        let mut error = match self.eval_catching(body) {
+            Err(error) => error,
+            Ok(value) => return Ok(Tail::Done(value)),
+        };
+
+        self.env = self.new_frame();
+        self.env.define(name, error.to_data());
+        match self.eval_cond(clauses)? {
+            Some(tail) => Ok(tail),
+            // The handlers outside the guard haven't seen it yet
+            None => {
+                error.handlers_called = false;
+                Err(self.raise(error))
+            }
+        }
+    }
+
+    /// `(try body... (catch name handler...) (finally cleanup...))`, where
+    /// the catch clause is evaluated with the error bound to `name` if the
+    /// body raises one, and the finally clause is always evaluated last.
+    /// Both clauses are optional.
+    fn eval_try(&mut self, operands: &[Data]) -> EvalResult {
+        let mut body = operands;
+        let mut catch = None;
+        let mut finally = None;
+        while let Some((clause, rest)) = body.split_last() {
+            let clause = match form_items(clause) {
+                Some(clause) => clause,
+                None => break,
+            };
+            match clause.split_first() {
+                Some((Data::Symbol(s), cleanup))
+                    if *s == self.keywords.finally && finally.is_none() && catch.is_none() =>
+                {
+                    finally = Some(cleanup.to_vec());
+                }
+                Some((Data::Symbol(s), handler))
+                    if *s == self.keywords.catch && catch.is_none() =>
+                {
+                    match handler.split_first() {
+                        Some((Data::Symbol(name), handler)) => {
+                            catch = Some((*name, handler.to_vec()))
+                        }
+                        _ => return Err(syntax_error("try", "catch needs a variable name")),
+                    }
+                }
+                _ => break,
+            }
+            body = rest;
+        }
+
+        let mut result = match catch {
+            Some(_) => self.eval_catching(body),
+            None => self.eval_body(body),
+        };
+        if let (Err(error), Some((name, handler))) = (&result, catch) {
+            let frame = self.new_frame();
+            frame.define(name, error.to_data());
+            result = self.eval_in(frame, &handler);
+        }
+
+        if let Some(cleanup) = finally {
+            self.eval_body(&cleanup)?;
+        }
+        result
+    }
+
+    /// `(define name value)` or `(define (name params...) body...)`, which
+    /// binds the name in the current frame.
+    fn eval_define(&mut self, operands: &[Data]) -> EvalResult {
+        let (name, value) = match operands {
+            [Data::Symbol(name), value] => (*name, self.eval(Ok(value.clone()))?),
+            [signature @ (Data::List(_) | Data::Pair(_)), body @ ..] => {
+                // (name a b . rest) has the parameter list (a b . rest)
+                let (signature, rest) = signature.list_parts();
+                match signature.split_first() {
+                    Some((Data::Symbol(name), params)) => {
+                        let mut lambda = vec![Data::list_with_tail(params.to_vec(), rest)];
+                        lambda.extend_from_slice(body);
+                        (*name, self.make_lambda(Some(*name), &lambda)?)
+                    }
+                    _ => return Err(syntax_error("define", "invalid function signature")),
+                }
+            }
+            _ => return Err(syntax_error("define", "expected a name and a value")),
+        };
+
+        self.env.define(name, value);
+        Ok(Data::Nil)
+    }
+
+    /// `(set! name value)`, which changes the value of an existing binding
+    /// in the innermost frame that has it.
+    fn eval_set(&mut self, operands: &[Data]) -> EvalResult {
+        let (name, value) = match operands {
+            [Data::Symbol(name), value] => (*name, self.eval(Ok(value.clone()))?),
+            _ => return Err(syntax_error("set!", "expected a name and a value")),
+        };
+
+        if self.env.set(name, value) {
+            return Ok(Data::Nil);
+        }
+
+        Err(Error::new(
+            ErrorKind::UnboundVariable,
+            format!(r#"set!: could not find variable "{}""#, name),
+        ))
+    }
+
+    /// `(let ((name value)...) body...)`, where the values are evaluated
+    /// before any of the names are bound. Also supports the named let
+    /// `(let loop ((name value)...) body...)`, which binds `loop` to a
+    /// function taking the names as parameters and calls it.
+    fn eval_let(&mut self, operands: &[Data]) -> Result<Tail, Error> {
+        if let Some(Data::Symbol(loop_name)) = operands.first() {
+            return self.eval_named_let(*loop_name, &operands[1..]);
+        }
+
+        let (bindings, body) = Interpreter::split_bindings("let", operands)?;
+        let frame = self.new_frame();
+        for (name, value) in bindings {
+            frame.define(name, self.eval(Ok(value))?);
+        }
+
+        self.env = frame;
+        self.eval_body_tail(body)
+    }
+
+    fn eval_named_let(&mut self, loop_name: Symbol, operands: &[Data]) -> Result<Tail, Error> {
+        let (bindings, body) = Interpreter::split_bindings("let", operands)?;
+        let mut params = Vec::new();
+        let mut args = Vec::new();
+        for (name, value) in bindings {
+            params.push(Data::Symbol(name));
+            args.push(self.eval(Ok(value))?);
+        }
+
+        self.env = self.new_frame();
+        let mut lambda = vec![Data::List(params.into())];
+        lambda.extend_from_slice(body);
+        let function = self.make_lambda(Some(loop_name), &lambda)?;
+        self.env.define(loop_name, function.clone());
+        match function {
+            Data::LispFunction(lambda) => Ok(Tail::Call(lambda, args, None)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// `(let* ((name value)...) body...)`, where each value can refer to
+    /// the names bound before it.
+    fn eval_let_star(&mut self, operands: &[Data]) -> Result<Tail, Error> {
+        let (bindings, body) = Interpreter::split_bindings("let*", operands)?;
+        self.env = self.new_frame();
+
+        for (name, value) in bindings {
+            let value = self.eval(Ok(value))?;
+            self.env.define(name, value);
+        }
+        self.eval_body_tail(body)
+    }
+
+    /// `(letrec ((name value)...) body...)`, where all names are bound
+    /// (to nil) before evaluating the values, so they can refer to each
+    /// other, as in mutually recursive functions.
+    fn eval_letrec(&mut self, operands: &[Data]) -> Result<Tail, Error> {
+        let (bindings, body) = Interpreter::split_bindings("letrec", operands)?;
+        self.env = self.new_frame();
+        for (name, _) in &bindings {
+            self.env.define(*name, Data::Nil);
+        }
+
+        for (name, value) in bindings {
+            let value = self.eval(Ok(value))?;
+            self.env.define(name, value);
+        }
+        self.eval_body_tail(body)
+    }
+
+    /// Splits the operands of a `let`-like form into its (still
+    /// unevaluated) bindings and its body.
+    fn split_bindings<'b>(
+        form: &str,
+        operands: &'b [Data],
+    ) -> Result<(Bindings, &'b [Data]), Error> {
+        let (bindings, body) = match operands.split_first() {
+            Some((bindings, body)) => match form_items(bindings) {
+                Some(bindings) => (bindings, body),
+                None => return Err(syntax_error(form, "missing binding list")),
+            },
+            None => return Err(syntax_error(form, "missing binding list")),
+        };
+
+        let mut result = Vec::new();
+        for binding in bindings.iter() {
+            match form_items(binding).as_deref() {
+                Some([Data::Symbol(name), value]) => result.push((*name, value.clone())),
+                _ => {
+                    return Err(syntax_error(
+                        form,
+                        &format!("invalid binding: {}", binding.repr()),
+                    ))
+                }
+            }
+        }
+
+        Ok((result, body))
+    }
+
+    /// Builds a closure out of the operands of a `lambda` form, which are
+    /// the parameter list followed by the body. The parameter list can
+    /// have a rest parameter after a dot, as in `(a b . rest)`, or after
+    /// `&rest`, or be a single symbol that receives all the arguments.
+    fn make_lambda(&mut self, name: Option<Symbol>, operands: &[Data]) -> EvalResult {
+        let (params, body) = match operands.split_first() {
+            Some(split) => split,
+            None => return Err(syntax_error("lambda", "missing parameter list")),
+        };
+
+        let mut lambda = Lambda {
+            name: name.map(|name| name.to_string()),
+            params: Vec::new(),
+            rest: None,
+            body: body.to_vec(),
+            env: self.env.clone(),
+        };
+
+        let (names, rest) = params.list_parts();
+        match rest {
+            Data::Symbol(rest) => lambda.rest = Some(rest),
+            rest if rest.is_null() => (),
+            _ => {
+                return Err(syntax_error(
+                    "lambda",
+                    &format!("invalid parameter list: {}", params.repr()),
+                ))
+            }
+        }
+
+        let mut names = names.iter();
+        while let Some(param) = names.next() {
+            match param {
+                Data::Symbol(s) if *s == self.keywords.rest => match (names.next(), names.next()) {
+                    (Some(Data::Symbol(rest)), None) if lambda.rest.is_none() => {
+                        lambda.rest = Some(*rest)
+                    }
+                    _ => {
+                        return Err(syntax_error(
+                            "lambda",
+                            "&rest must be followed by exactly one symbol",
+                        ))
+                    }
+                },
+                Data::Symbol(s) => lambda.params.push(*s),
+                x => {
+                    return Err(syntax_error(
+                        "lambda",
+                        &format!("invalid parameter: {}", x.repr()),
+                    ))
+                }
+            }
+        }
+
+        Ok(Data::LispFunction(Rc::new(lambda)))
+    }
+
+    /// Switches to a new frame on top of the environment a closure
+    /// captured, with its parameters bound to `args` (whose count was
+    /// already checked), for its body to be evaluated in.
+    fn enter_lambda(&mut self, lambda: &Lambda, args: &[Data]) {
+        let frame = Environment::extend(&lambda.env);
+        for (param, arg) in lambda.params.iter().zip(args) {
+            frame.define(*param, arg.clone());
+        }
+        if let Some(rest) = lambda.rest {
+            frame.define(rest, Data::list(args[lambda.params.len()..].to_vec()));
+        }
+
+        self.env = frame;
+    }
+}
+
+/// The items of a list in a form, which is a list literal unless the code
+/// was built at runtime out of pairs.
+fn form_items(data: &Data) -> Option<Cow<'_, [Data]>> {
+    match data {
+        Data::List(list) => Some(Cow::Borrowed(&list[..])),
+        Data::Pair(_) => data.list_items().map(Cow::Owned),
+        _ => None,
+    }
+}
+
+/// The error for a special form written with the wrong shape.
+fn syntax_error(form: &str, message: &str) -> Error {
+    Error::new(ErrorKind::Syntax, format!("{}: {}", form, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::parser::parse_program;
+    use crate::lib::span::Source;
+
+    /// Evaluates `code` in a new interpreter and writes the value of its
+    /// last form.
+    fn eval_code(code: &str) -> Result<String, Error> {
+        let mut interpreter = Interpreter::new(Vec::new());
+        let mut result = Data::Nil;
+        for form in parse_program(&Source::new("<test>", code))? {
+            result = interpreter.eval_form(form)?;
+        }
+        Ok(result.repr())
+    }
+
+    /// Evaluates `body` with an exception handler that returns, inside a
+    /// `try` with a finally clause, and gives the order things happened.
+    fn handler_order(body: &str) -> String {
+        let code = format!(
+            "(define log '())
+             (define (note x) (set! log (cons x log)))
+             (try
+               (with-exception-handler
+                 (lambda (e) (note 'handler) 0)
+                 (lambda () (try {} (finally (note 'finally)))))
+               (catch e (reverse log)))",
+            body
+        );
+        eval_code(&code).unwrap()
+    }
+
+    #[test]
+    fn handlers_run_before_finally_clauses() {
+        assert_eq!(handler_order("(raise 'x)"), "(handler finally)");
+        assert_eq!(handler_order("(error \"x\")"), "(handler finally)");
+        assert_eq!(handler_order("(car 1)"), "(handler finally)");
+        assert_eq!(handler_order("undefined-variable"), "(handler finally)");
+    }
+
+    #[test]
+    fn handlers_run_with_the_outer_handlers() {
+        let code = "(define calls 0)
+                    (guard (e (#t (list calls e)))
+                      (with-exception-handler
+                        (lambda (e) (set! calls (+ calls 1)) (raise 'y))
+                        (lambda () (raise-continuable 'x))))";
+        assert_eq!(eval_code(code).unwrap(), "(1 y)");
+    }
+
+    #[test]
+    fn continuable_raises_return() {
+        let code = "(with-exception-handler
+                      (lambda (e) 42)
+                      (lambda () (+ 1 (raise-continuable 'c))))";
+        assert_eq!(eval_code(code).unwrap(), "43");
+    }
+
+    #[test]
+    fn guard_catches_before_outer_handlers() {
+        let code = "(with-exception-handler
+                      (lambda (e) (raise 'outer))
+                      (lambda () (guard (e ((symbol? e) (list 'inner e))) (raise 'x))))";
+        assert_eq!(eval_code(code).unwrap(), "(inner x)");
+    }
+
+    #[test]
+    fn tail_calls_dont_nest() {
+        let code = "(define (count n acc)
+                      (cond ((= n 0) acc)
+                            (else (let ((m (- n 1)))
+                                    (begin (and #t (or #f (count m (+ acc 1)))))))))
+                    (count 100000 0)";
+        assert_eq!(eval_code(code).unwrap(), "100000");
+    }
+
+    #[test]
+    fn mutual_tail_calls_dont_nest() {
+        let code = "(define (even n) (if (= n 0) #t (odd (- n 1))))
+                    (define (odd n) (if (= n 0) #f (even (- n 1))))
+                    (even 100001)";
+        assert_eq!(eval_code(code).unwrap(), "#f");
+    }
+
+    #[test]
+    fn recursion_limit() {
+        let mut interpreter = Interpreter::new(Vec::new());
+        interpreter.set_max_depth(100);
+        let code = "(define (deep n) (if (= n 0) 0 (+ 1 (deep (- n 1)))))
+                    (list (deep 10)
+                          (guard (e (#t (error-object-message e))) (deep 1000)))";
+        let mut result = Data::Nil;
+        for form in parse_program(&Source::new("<test>", code)).unwrap() {
+            result = interpreter.eval_form(form).unwrap();
+        }
+        assert_eq!(
+            result.repr(),
+            r#"(10 "maximum recursion depth exceeded (100)")"#
+        );
+
+        let form = parse_program(&Source::new("<test>", "(deep 1000)")).unwrap();
+        let error = interpreter.eval_form(form[0].clone()).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::RecursionLimit);
+        // The depth is back to zero after the error
+        let form = parse_program(&Source::new("<test>", "(deep 90)")).unwrap();
+        assert_eq!(interpreter.eval_form(form[0].clone()).unwrap().repr(), "90");
+    }
+
+    #[test]
+    fn quasiquote() {
+        let code = "(define x 5) (define xs '(6 7))
+                    (list `(1 ,x ,@xs) `(1 . ,x) `(1 ,@xs . ,x) `(1 . 2) `,x)";
+        assert_eq!(
+            eval_code(code).unwrap(),
+            "((1 5 6 7) (1 . 5) (1 6 7 . 5) (1 . 2) 5)"
+        );
+    }
+
+    #[test]
+    fn nested_quasiquote() {
+        let code = "(define x 5) `(1 `(2 ,(3 ,x)))";
+        assert_eq!(eval_code(code).unwrap(), "(1 `(2 ,(3 5)))");
+    }
+
+    #[test]
+    fn quasiquote_built_at_runtime() {
+        let code = "(define x 5)
+                    (eval (list 'quasiquote (cons 1 (list 'unquote 'x))))";
+        assert_eq!(eval_code(code).unwrap(), "(1 . 5)");
+    }
 
-        None
+    #[test]
+    fn dotted_parameter_lists() {
+        let code = "(define (f a . rest) (list a rest)) (f 1 2 3)";
+        assert_eq!(eval_code(code).unwrap(), "(1 (2 3))");
     }
 }
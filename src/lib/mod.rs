@@ -1,4 +1,9 @@
 pub mod data;
+pub mod environment;
+pub mod error;
 pub mod interpreter;
 pub mod parser;
-// pub mod repl;
+pub mod repl;
+pub mod span;
+pub mod stdlib;
+pub mod symbol;
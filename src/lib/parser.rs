@@ -1,41 +1,249 @@
-use crate::lib::data::DataPre;
+use crate::lib::data::{DataPre, DataPreKind, CHAR_NAMES};
+use crate::lib::error::{self, ErrorKind};
+use crate::lib::span::{Source, Span};
 use crate::pest::{
+    error::{Error, ErrorVariant, InputLocation},
     iterators::{Pair, Pairs},
     Parser,
 };
+use num_bigint::BigInt;
+use num_rational::BigRational;
+use num_traits::{Pow, ToPrimitive, Zero};
+use std::rc::Rc;
 
 #[derive(Parser)]
 #[grammar = "lib/lisp.pest"]
 struct LispParser;
 
-fn pairs_to_data(pair: Pair<Rule>) -> DataPre {
-    match pair.as_rule() {
+type ParseResult<T> = Result<T, Error<Rule>>;
+
+fn pairs_to_data(pair: Pair<Rule>, source: &Rc<Source>) -> ParseResult<DataPre> {
+    let span = make_span(&pair, source);
+    let kind = match pair.as_rule() {
         Rule::expr => {
             let mut inner = pair.into_inner();
             let inner_str = inner.as_str();
             match inner.clone().next().unwrap().as_rule() {
                 // unwrap() is safe because expr's only contain one inner element
-                Rule::list => DataPre::List(
-                    inner
-                        .next()
-                        .unwrap()
+                Rule::list => {
+                    let list = inner.next().unwrap();
+                    let items = pairs_to_data_list(list.clone().into_inner(), source)?;
+                    let dotted_tail = list
+                        .clone()
                         .into_inner()
-                        .map(pairs_to_data)
-                        .collect(),
-                ),
-                Rule::int => DataPre::Int(inner_str.to_string()),
-                Rule::float => DataPre::Float(inner_str.to_string()),
-                Rule::string => DataPre::Str(parse_string(inner)),
-                Rule::symbol => DataPre::Symbol(inner_str.to_string()),
+                        .find(|pair| pair.as_rule() == Rule::dotted_tail);
+                    match dotted_tail {
+                        None => DataPreKind::List(items),
+                        Some(_) if items.is_empty() => {
+                            return Err(custom_error(&list, "expected a datum before the dot"))
+                        }
+                        Some(tail) => {
+                            // The tail's only datum, without the commented out ones
+                            let tail = pairs_to_data_list(tail.into_inner(), source)?.remove(0);
+                            DataPreKind::DottedList(items, Box::new(tail))
+                        }
+                    }
+                }
+                Rule::vector => DataPreKind::Vector(pairs_to_data_list(
+                    inner.next().unwrap().into_inner(),
+                    source,
+                )?),
+                Rule::quoted => {
+                    let mut quoted = inner.next().unwrap().into_inner();
+                    let prefix = quoted.next().unwrap();
+                    let name = match prefix.as_str() {
+                        "'" => "quote",
+                        "`" => "quasiquote",
+                        "," => "unquote",
+                        ",@" => "unquote-splicing",
+                        any_other => unreachable!("quote prefix: {:?}", any_other),
+                    };
+                    DataPreKind::List(vec![
+                        DataPre {
+                            kind: DataPreKind::Symbol(name.into()),
+                            span: make_span(&prefix, source),
+                        },
+                        pairs_to_data(quoted.next().unwrap(), source)?,
+                    ])
+                }
+                Rule::number => parse_number(&inner.next().unwrap())?,
+                Rule::boolean => DataPreKind::Bool(inner_str.starts_with("#t")),
+                Rule::character => DataPreKind::Char(parse_character(inner.next().unwrap())?),
+                Rule::string => DataPreKind::Str(parse_string(inner)?),
+                Rule::interpolated_string => {
+                    parse_interpolated_string(inner.next().unwrap(), source)?
+                }
+                Rule::symbol => DataPreKind::Symbol(inner_str.to_string()),
                 any_other => unreachable!("inside expr: {:?}", any_other),
             }
         }
-        Rule::EOI => DataPre::Nil, // The end of the file is simply ignored
+        Rule::EOI => DataPreKind::Nil, // The end of the file is simply ignored
         any_other => unreachable!("{:?}", any_other),
+    };
+    Ok(DataPre { kind, span })
+}
+
+/// Converts a sequence of expressions, leaving out the ones commented out
+/// with `#;`, the shebang line and the dotted tail of a list (which is
+/// converted on its own).
+fn pairs_to_data_list(pairs: Pairs<Rule>, source: &Rc<Source>) -> ParseResult<Vec<DataPre>> {
+    pairs
+        .filter(|pair| {
+            !matches!(
+                pair.as_rule(),
+                Rule::datum_comment | Rule::shebang | Rule::dotted_tail | Rule::dot
+            )
+        })
+        .map(|pair| pairs_to_data(pair, source))
+        .collect()
+}
+
+/// The part of `source` that `pair` was parsed from.
+fn make_span(pair: &Pair<Rule>, source: &Rc<Source>) -> Span {
+    let span = pair.as_span();
+    Span {
+        source: source.clone(),
+        start: span.start(),
+        end: span.end(),
     }
 }
 
-fn parse_string(string_data: Pairs<Rule>) -> String {
+/// Makes a parsing error pointing at `pair`, for input that matches the
+/// grammar but is still invalid.
+fn custom_error(pair: &Pair<Rule>, message: &str) -> Error<Rule> {
+    Error::new_from_span(
+        ErrorVariant::CustomError {
+            message: message.into(),
+        },
+        pair.as_span(),
+    )
+}
+
+/// Converts a number literal to the `DataPreKind` number it's read as,
+/// applying its radix and exactness prefixes.
+fn parse_number(number: &Pair<Rule>) -> ParseResult<DataPreKind> {
+    let mut text = number.as_str();
+    let mut radix = 10;
+    let mut exact = None;
+    while text.starts_with('#') {
+        match text.as_bytes()[1].to_ascii_lowercase() {
+            b'x' => radix = 16,
+            b'o' => radix = 8,
+            b'b' => radix = 2,
+            b'd' => radix = 10,
+            b'e' => exact = Some(true),
+            b'i' => exact = Some(false),
+            any_other => unreachable!("number prefix: {:?}", any_other as char),
+        }
+        text = &text[2..];
+    }
+    let text = text.replace('_', "").to_ascii_lowercase();
+
+    let is_float = radix == 10 && (text.contains('.') || text.contains('e'));
+    if is_float {
+        let float = match text.as_str() {
+            "+inf.0" => "inf",
+            "-inf.0" => "-inf",
+            "+nan.0" | "-nan.0" => "NaN",
+            float => float,
+        };
+        // A prefixed number's digits aren't checked by the grammar, as in
+        // #d1e
+        let float = float
+            .parse::<f64>()
+            .map_err(|_| custom_error(number, "invalid decimal number"))?;
+        if exact != Some(true) {
+            return Ok(DataPreKind::Float(float));
+        }
+    }
+
+    let value = if is_float {
+        exact_decimal(&text)
+            .ok_or_else(|| custom_error(number, "number has no exact representation"))?
+    } else {
+        let (negative, digits) = match text.as_bytes()[0] {
+            b'-' => (true, &text[1..]),
+            b'+' => (false, &text[1..]),
+            _ => (false, &text[..]),
+        };
+        let parse_digits = |digits: &str| {
+            BigInt::parse_bytes(digits.as_bytes(), radix).ok_or_else(|| {
+                custom_error(
+                    number,
+                    &format!("invalid digit for a base {} number", radix),
+                )
+            })
+        };
+        let value = match digits.split_once('/') {
+            Some((numerator, denominator)) => {
+                let denominator = parse_digits(denominator)?;
+                if denominator.is_zero() {
+                    return Err(custom_error(number, "zero denominator in rational"));
+                }
+                BigRational::new(parse_digits(numerator)?, denominator)
+            }
+            None => BigRational::from_integer(parse_digits(digits)?),
+        };
+        if negative {
+            -value
+        } else {
+            value
+        }
+    };
+
+    Ok(match exact {
+        Some(false) => DataPreKind::Float(value.to_f64().unwrap_or(f64::NAN)),
+        _ if value.is_integer() => DataPreKind::Int(value.to_integer().to_string()),
+        _ => DataPreKind::Rational(value.to_string()),
+    })
+}
+
+/// The largest power of ten `exact_decimal` is willing to compute.
+const MAX_EXACT_EXPONENT: u32 = 100_000;
+
+/// The exact value of a decimal float literal like "-1.25e3", or `None`
+/// for infinities, NaNs and absurdly large exponents.
+fn exact_decimal(text: &str) -> Option<BigRational> {
+    let (mantissa, exponent) = match text.split_once('e') {
+        Some((mantissa, exponent)) => (mantissa, exponent.parse::<i32>().ok()?),
+        None => (text, 0),
+    };
+    let (integer_part, fraction_part) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+    let digits = format!("{}{}", integer_part, fraction_part);
+    let digits: BigInt = digits.trim_start_matches('+').parse().ok()?;
+
+    let scale = exponent.checked_sub(fraction_part.len() as i32)?;
+    if scale.unsigned_abs() > MAX_EXACT_EXPONENT {
+        return None;
+    }
+    let power = BigInt::from(10).pow(scale.unsigned_abs());
+    Some(if scale >= 0 {
+        BigRational::from_integer(digits * power)
+    } else {
+        BigRational::new(digits, power)
+    })
+}
+
+fn parse_character(character: Pair<Rule>) -> ParseResult<char> {
+    let inner = character.into_inner().next().unwrap();
+    let text = inner.as_str();
+
+    match inner.as_rule() {
+        Rule::char_hex => u32::from_str_radix(&text[1..], 16)
+            .ok()
+            .and_then(std::char::from_u32)
+            .ok_or_else(|| custom_error(&inner, "invalid character code")),
+        Rule::char_name if text.chars().count() == 1 => Ok(text.chars().next().unwrap()),
+        Rule::char_name => match CHAR_NAMES.iter().find(|(name, _)| *name == text) {
+            Some((_, c)) => Ok(*c),
+            None => Err(custom_error(&inner, "unknown character name")),
+        },
+        Rule::char_any => Ok(text.chars().next().unwrap()),
+        any_other => unreachable!("inside character: {:?}", any_other),
+    }
+}
+
+fn parse_string(string_data: Pairs<Rule>) -> ParseResult<String> {
     let chars = string_data
         .clone() // string
         .next()
@@ -44,53 +252,256 @@ fn parse_string(string_data: Pairs<Rule>) -> String {
         .next()
         .unwrap()
         .into_inner(); // char*
+    parse_chars(chars)
+}
+
+/// Reads `#"a ${b} c"` as `(format "a ~a c" b)`, escaping the tildes of
+/// the text around the interpolations.
+fn parse_interpolated_string(string: Pair<Rule>, source: &Rc<Source>) -> ParseResult<DataPreKind> {
+    let span = make_span(&string, source);
+    let mut template = String::new();
+    let mut values = Vec::new();
+    let mut chars = Vec::new();
+    for part in string.into_inner() {
+        match part.as_rule() {
+            Rule::char => chars.push(part),
+            Rule::interpolation => {
+                template.push_str(&parse_chars(chars.drain(..))?.replace('~', "~~"));
+                template.push_str("~a");
+                values.push(pairs_to_data(part.into_inner().next().unwrap(), source)?);
+            }
+            any_other => unreachable!("inside interpolated string: {:?}", any_other),
+        }
+    }
+    template.push_str(&parse_chars(chars.drain(..))?.replace('~', "~~"));
+
+    let mut form = vec![
+        DataPre {
+            kind: DataPreKind::Symbol("format".into()),
+            span: span.clone(),
+        },
+        DataPre {
+            kind: DataPreKind::Str(template),
+            span,
+        },
+    ];
+    form.extend(values);
+    Ok(DataPreKind::List(form))
+}
+
+/// Reads the characters of a string literal, processing its escapes.
+fn parse_chars<'i, I: Iterator<Item = Pair<'i, Rule>>>(chars: I) -> ParseResult<String> {
     let mut final_string = String::new();
+    // The first half of a surrogate pair written as two \uXXXX escapes
+    let mut high_surrogate: Option<(u32, Pair<Rule>)> = None;
 
     for ch in chars {
         let ch_data = ch.into_inner().next().unwrap();
         let ch_rule = ch_data.clone().as_rule();
         let ch_str = ch_data.clone().as_str();
 
+        let code = match ch_rule {
+            Rule::char_escape_unicode => {
+                let digits = ch_str[2..].trim_matches(|c| c == '{' || c == '}');
+                Some(u32::from_str_radix(digits, 16).unwrap())
+            }
+            _ => None,
+        };
+
+        if let Some((high, high_data)) = high_surrogate.take() {
+            match code {
+                Some(low @ 0xDC00..=0xDFFF) => {
+                    let combined = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+                    final_string.push(std::char::from_u32(combined).unwrap());
+                    continue;
+                }
+                _ => return Err(custom_error(&high_data, "unpaired surrogate in escape")),
+            }
+        }
+
         match ch_rule {
             Rule::char_normal => final_string.push_str(ch_str),
             Rule::char_escape_code => final_string.push(match ch_str {
                 "\\n" => '\n',
                 "\\t" => '\t',
+                "\\r" => '\r',
+                "\\b" => '\x08',
+                "\\v" => '\x0b',
+                "\\a" => '\x07',
+                "\\f" => '\x0c',
+                "\\0" => '\0',
                 "\\\"" => '\"',
+                "\\$" => '$',
                 "\\\\" => '\\',
-                _ => unreachable!("this escape code should not be here: '{}'", ch_str),
+                _ => return Err(custom_error(&ch_data, "invalid escape sequence")),
             }),
-            // I'm struggling to implement this one:
-            // Rule::char_unicode_hex => {
-            //     for byte_step in 0..1 {
-            //         final_string.push(
-            //             std::char::from_u32(
-            //                 u32::from_str_radix(&ch_str[(2 + byte_step)..(4 + byte_step)], 16)
-            //                     .unwrap(),
-            //             )
-            //             .unwrap(),
-            //         )
-            //     }
-            // }
+            Rule::char_escape_unicode => match code.unwrap() {
+                high @ 0xD800..=0xDBFF if !ch_str.contains('{') => {
+                    high_surrogate = Some((high, ch_data));
+                }
+                code => match std::char::from_u32(code) {
+                    Some(c) => final_string.push(c),
+                    None => return Err(custom_error(&ch_data, "invalid unicode escape")),
+                },
+            },
+            Rule::char_escape_hex => {
+                match u32::from_str_radix(&ch_str[2..ch_str.len() - 1], 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                {
+                    Some(c) => final_string.push(c),
+                    None => return Err(custom_error(&ch_data, "invalid hex escape")),
+                }
+            }
+            Rule::char_line_continuation => (), // The line break is left out
             _ => unreachable!("{:?}", ch_rule),
         }
     }
 
-    final_string
+    match high_surrogate {
+        Some((_, high_data)) => Err(custom_error(&high_data, "unpaired surrogate in escape")),
+        None => Ok(final_string),
+    }
+}
+
+/// Parses a whole program. Every datum remembers where in the source it
+/// was read from.
+pub fn parse_program(source: &Rc<Source>) -> Result<Vec<DataPre>, error::Error> {
+    let parsed = LispParser::parse(Rule::program, &source.text).and_then(|mut program| {
+        let mut data = pairs_to_data_list(program.next().unwrap().into_inner(), source)?;
+        data.pop(); // remove the DataPreKind::Nil resulted from EOI
+        Ok(data)
+    });
+    parsed.map_err(|e| convert_error(e, source))
+}
+
+/// Reads `text` as a number literal, with the same syntax as in the
+/// source code. Returns `None` if it's anything else.
+pub fn parse_number_literal(text: &str) -> Option<DataPre> {
+    let source = Source::new("<string>", text);
+    let number = LispParser::parse(Rule::number, text).ok()?.next()?;
+    if number.as_span().end() != text.len() {
+        return None;
+    }
+    Some(DataPre {
+        kind: parse_number(&number).ok()?,
+        span: make_span(&number, &source),
+    })
 }
 
-pub fn parse_program(program: &str) -> Result<Vec<DataPre>, pest::error::Error<Rule>> {
-    match LispParser::parse(Rule::program, program) {
-        Ok(mut program) => {
-            let mut data: Vec<DataPre> = program
-                .next()
-                .unwrap()
-                .into_inner()
-                .map(pairs_to_data)
-                .collect();
-            data.pop(); // remove DataPre::Nil resulted from EOI
-            Ok(data)
+/// Turns a pest error into one of ours, pointing at the same place.
+fn convert_error(e: Error<Rule>, source: &Rc<Source>) -> error::Error {
+    let (start, end) = match e.location {
+        InputLocation::Pos(pos) => (pos, pos),
+        InputLocation::Span(span) => span,
+    };
+    let mut error = error::Error::new(ErrorKind::Parse, e.variant.message());
+    error.span = Some(Span {
+        source: source.clone(),
+        start,
+        end,
+    });
+    error
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::data::Data;
+
+    /// Reads the only datum of `text` and writes it back.
+    fn read(text: &str) -> String {
+        let mut program = parse_program(&Source::new("<test>", text)).unwrap();
+        assert_eq!(program.len(), 1, "{:?}", text);
+        Data::from(program.remove(0)).repr()
+    }
+
+    fn read_error(text: &str) -> String {
+        parse_program(&Source::new("<test>", text))
+            .unwrap_err()
+            .message
+    }
+
+    #[test]
+    fn big_integers() {
+        assert_eq!(read("9223372036854775808"), "9223372036854775808");
+        assert_eq!(read("-99999999999999999999"), "-99999999999999999999");
+    }
+
+    #[test]
+    fn rationals() {
+        assert_eq!(read("1/3"), "1/3");
+        assert_eq!(read("-6/4"), "-3/2");
+        assert_eq!(read("4/2"), "2");
+        assert_eq!(read_error("1/0"), "zero denominator in rational");
+    }
+
+    #[test]
+    fn exactness_prefixes() {
+        assert_eq!(read("#e1.5"), "3/2");
+        assert_eq!(read("#e1e3"), "1000");
+        assert_eq!(read("#i1/3"), "0.3333333333333333");
+        assert_eq!(read("#x#e10"), "16");
+    }
+
+    #[test]
+    fn radix_prefixes() {
+        assert_eq!(read("#x1F"), "31");
+        assert_eq!(read("#b-101"), "-5");
+        assert_eq!(read("#o17/2"), "15/2");
+        assert_eq!(read_error("#xZZ"), "invalid digit for a base 16 number");
+        assert_eq!(read_error("#b102"), "invalid digit for a base 2 number");
+    }
+
+    #[test]
+    fn malformed_prefixed_decimals() {
+        for text in ["#i1e", "#d1e", "#d1eee", "#e1e"].iter() {
+            assert_eq!(read_error(text), "invalid decimal number", "{}", text);
         }
-        Err(e) => Err(e),
+        assert!(parse_number_literal("#i1e").is_none());
+    }
+
+    #[test]
+    fn exponents() {
+        assert_eq!(read("1e10"), "10000000000.0");
+        assert_eq!(read("6.02E23"), "6.02e23");
+        assert_eq!(read(".5e-3"), "0.0005");
+        assert_eq!(read("+1.5"), "1.5");
+    }
+
+    #[test]
+    fn special_floats() {
+        assert_eq!(read("+inf.0"), "+inf.0");
+        assert_eq!(read("-inf.0"), "-inf.0");
+        assert_eq!(read("+nan.0"), "+nan.0");
+    }
+
+    #[test]
+    fn digit_separators() {
+        assert_eq!(read("1_000"), "1000");
+        assert_eq!(read("1_000.5"), "1000.5");
+        assert_eq!(read("#xFF_FF"), "65535");
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(read(r#""a\tb\nc""#), r#""a\tb\nc""#);
+        assert_eq!(read(r#""\x41;\a""#), r#""A\u{7}""#);
+        assert_eq!(read("\"a\\\n    b\""), r#""ab""#);
+        assert_eq!(read_error(r#""\q""#), "invalid escape sequence");
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        assert_eq!(read(r#""\u00e9""#), r#""é""#);
+        assert_eq!(read(r#""\u{1F600}""#), r#""😀""#);
+        assert_eq!(read(r#""\uD83D\uDE00""#), r#""😀""#);
+    }
+
+    #[test]
+    fn unpaired_surrogates() {
+        assert_eq!(read_error(r#""\uD83D""#), "unpaired surrogate in escape");
+        assert_eq!(read_error(r#""\uD83Dx""#), "unpaired surrogate in escape");
+        assert_eq!(read_error(r#""\uDE00""#), "invalid unicode escape");
     }
 }
@@ -1,56 +1,121 @@
 /// A REPL (or Read, Evaluate, Print, Loop) is a program that can get
 /// input from the user, process that in a certain language, and return
 /// the results to the user.
-
+use crate::lib::{data::Data, interpreter::Interpreter, parser::parse_program, span::Source};
 use std::io::{self, Write};
-use crate::lib::{
-    lexer::Lexer,
-    parser::{parse_tokens, ParserResult, Expression},
-};
 
-const INDENT_SIZE: usize = 2;
+const PROMPT: &str = "crisp> ";
+const CONTINUATION_PROMPT: &str = "  ...> ";
 
 /**
- * Initializes the REPL and processes the user the user input until it
- * SIGINT (^C) is sent to the terminal.
+ * Initializes the REPL and processes the user input until the end of
+ * the input (^D) is reached or SIGINT (^C) is sent to the terminal.
+ *
+ * The same interpreter is used for the whole session, so definitions
+ * made on one line are still available on the next ones.
  */
 pub fn init() {
-    loop {
-        let input = read_line("crisp> ");
-        let mut lex = Lexer::from(input.as_ref());
-        let tokens = lex.get_tokens();
-        match tokens {
-            Ok(tok) => print_expression_tree(&parse_tokens(&tok)),
-            Err(e) => println!("Lexer error: {:?}", e),
+    let mut interpreter = Interpreter::new(Vec::new());
+
+    while let Some(input) = read_expression() {
+        if input.trim().is_empty() {
+            continue;
+        }
+
+        let program = match parse_program(&Source::new("<repl>", &input)) {
+            Ok(program) => program,
+            Err(e) => {
+                println!("{}", e.render());
+                continue;
+            }
+        };
+
+        for pre in program {
+            match interpreter.eval_form(pre) {
+                Ok(Data::Nil) => (),
+                Ok(result) => println!("{}", result.repr()),
+                Err(e) => {
+                    println!("{}", e.render());
+                    break;
+                }
+            }
         }
     }
+
+    println!();
 }
 
-fn print_expression_tree(tree: &ParserResult) {
-    match tree {
-        Ok(t) => for item in t {
-            print_expression(item, 0);
-        },
-        Err(e) => println!("Parser error: {:?}", e),
+/**
+ * Reads lines until they form a complete expression (that is, until
+ * there are no unclosed parentheses or strings left). Returns `None` at
+ * the end of the input.
+ */
+fn read_expression() -> Option<String> {
+    let mut input = read_line(PROMPT)?;
+
+    while is_incomplete(&input) {
+        match read_line(CONTINUATION_PROMPT) {
+            Some(line) => {
+                input.push('\n');
+                input.push_str(&line);
+            }
+            None => break,
+        }
     }
+
+    Some(input)
 }
 
-fn print_expression(e: &Expression, indent: usize) {
-    match e {
-        Expression::List(l) => {
-            println!("{}* List{}", " ".repeat(INDENT_SIZE * indent), if l.len() == 0 { " [empty]" } else { ":" });
-            for expression in l {
-                print_expression(expression, indent + 1);
+/**
+ * Checks whether the input still has open parentheses, an unfinished
+ * string or an unfinished block comment, in which case more lines should
+ * be read.
+ */
+fn is_incomplete(input: &str) -> bool {
+    let mut depth = 0;
+    let mut comment_depth = 0;
+    let mut in_string = false;
+    let mut chars = input.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '|' if comment_depth > 0 && chars.peek() == Some(&'#') => {
+                chars.next();
+                comment_depth -= 1;
+            }
+            '#' if !in_string && chars.peek() == Some(&'|') => {
+                chars.next();
+                comment_depth += 1;
             }
-        },
-        _ => println!("{}* {:?}", " ".repeat(INDENT_SIZE * indent), e),
+            _ if comment_depth > 0 => (),
+            '\\' if in_string => {
+                chars.next();
+            }
+            '"' => in_string = !in_string,
+            _ if in_string => (),
+            ';' => {
+                // Line comment, skip to the end of the line
+                while chars.next().is_some_and(|ch| ch != '\n') {}
+            }
+            '#' if chars.peek() == Some(&'\\') => {
+                // Character literal, which could be #\( or #\)
+                chars.next();
+                chars.next();
+            }
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth -= 1,
+            _ => (),
+        }
     }
+
+    in_string || comment_depth > 0 || depth > 0
 }
 
 /**
- * Gets text input from the user and trims it.
+ * Gets text input from the user and trims it. Returns `None` at the end
+ * of the input.
  */
-fn read_line(prompt: &str) -> String {
+fn read_line(prompt: &str) -> Option<String> {
     let mut input = String::new();
 
     // Show the prompt
@@ -58,9 +123,16 @@ fn read_line(prompt: &str) -> String {
     io::stdout().flush().unwrap();
 
     // Actually read input
-    io::stdin().read_line(&mut input)
+    let bytes_read = io::stdin()
+        .read_line(&mut input)
         .expect("Error reading input");
 
-    // Return the trimmed String
-    input.trim().into()
+    if bytes_read == 0 {
+        None
+    } else {
+        // Only the line terminator is removed, since the line can be
+        // part of a multi-line string
+        let line = input.strip_suffix('\n').unwrap_or(&input);
+        Some(line.strip_suffix('\r').unwrap_or(line).into())
+    }
 }
@@ -0,0 +1,75 @@
+//! Positions in the source code, used to point at the code that caused an
+//! error.
+
+use std::fmt;
+use std::rc::Rc;
+
+/// A piece of source code: a script, an expression given on the command
+/// line, a line typed in the REPL...
+#[derive(Debug)]
+pub struct Source {
+    pub name: String,
+    pub text: String,
+}
+
+impl Source {
+    pub fn new(name: &str, text: &str) -> Rc<Source> {
+        Rc::new(Source {
+            name: name.into(),
+            text: text.into(),
+        })
+    }
+}
+
+/// A range of bytes in a source.
+#[derive(Clone)]
+pub struct Span {
+    pub source: Rc<Source>,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// The 1-based line and column where the span starts. Columns are
+    /// counted in characters.
+    pub fn line_column(&self) -> (usize, usize) {
+        let before = &self.source.text[..self.start];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let column = before[line_start..].chars().count() + 1;
+        (line, column)
+    }
+
+    /// Formats an error message rustc-style, followed by the line the span
+    /// starts at with a caret under the span (up to the end of the line).
+    pub fn render(&self, message: &str) -> String {
+        let (line, column) = self.line_column();
+        let text = &self.source.text;
+        let line_start = text[..self.start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = text[self.start..]
+            .find('\n')
+            .map_or(text.len(), |i| self.start + i);
+        let line_text = &text[line_start..line_end];
+        let marked = text[self.start..self.end.min(line_end)].chars().count();
+
+        let gutter = " ".repeat(line.to_string().len());
+        format!(
+            "error: {message}\n{gutter}--> {name}:{line}:{column}\n{gutter} |\n{line} | {line_text}\n{gutter} | {padding}{carets}",
+            message = message,
+            gutter = gutter,
+            name = self.source.name,
+            line = line,
+            column = column,
+            line_text = line_text.trim_end_matches('\r'),
+            padding = " ".repeat(column - 1),
+            carets = "^".repeat(marked.max(1)),
+        )
+    }
+}
+
+impl fmt::Debug for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (line, column) = self.line_column();
+        write!(f, "{}:{}:{}", self.source.name, line, column)
+    }
+}
@@ -0,0 +1,598 @@
+//! Arithmetic and comparison functions.
+//!
+//! Numbers are either exact (integers of any size and fractions) or
+//! inexact (floats). An operation is exact if all of its operands are
+//! exact, and done on floats otherwise. Integers that don't fit in an
+//! `i64` are automatically promoted to big integers, and dividing integers
+//! that aren't multiples of each other gives an exact fraction.
+
+use super::wrong_type;
+use crate::lib::data::{Arity, Data};
+use crate::lib::error::{Error, ErrorKind};
+use crate::lib::interpreter::{EvalResult, Interpreter};
+use num_bigint::BigInt;
+use num_integer::Integer;
+use num_rational::BigRational;
+use num_traits::{Signed, ToPrimitive, Zero};
+use std::cmp::Ordering;
+
+/// A number taken out of a `Data`, so the operations below don't have to
+/// deal with the other variants.
+#[derive(Debug, Clone)]
+enum Number {
+    Int(i64),
+    Big(BigInt),
+    Ratio(BigRational),
+    Float(f64),
+}
+
+impl Number {
+    fn from_data(function: &str, data: &Data) -> Result<Number, Error> {
+        match data {
+            Data::Int(i) => Ok(Number::Int(*i)),
+            Data::BigInt(i) => Ok(Number::Big(i.clone())),
+            Data::Rational(r) => Ok(Number::Ratio(r.clone())),
+            Data::Float(f) => Ok(Number::Float(*f)),
+            x => Err(wrong_type(function, x)),
+        }
+    }
+
+    /// Makes an exact number out of a fraction, using the smallest
+    /// representation for it.
+    fn from_rational(r: BigRational) -> Number {
+        if !r.is_integer() {
+            Number::Ratio(r)
+        } else {
+            let i = r.to_integer();
+            match i.to_i64() {
+                Some(i) => Number::Int(i),
+                None => Number::Big(i),
+            }
+        }
+    }
+
+    fn to_f64(&self) -> f64 {
+        match self {
+            Number::Int(i) => *i as f64,
+            Number::Big(i) => i.to_f64().unwrap_or(f64::NAN),
+            Number::Ratio(r) => r.to_f64().unwrap_or(f64::NAN),
+            Number::Float(f) => *f,
+        }
+    }
+
+    /// The value of an exact number as a fraction. Must not be called on
+    /// floats.
+    fn to_rational(&self) -> BigRational {
+        match self {
+            Number::Int(i) => BigRational::from_integer((*i).into()),
+            Number::Big(i) => BigRational::from_integer(i.clone()),
+            Number::Ratio(r) => r.clone(),
+            Number::Float(_) => unreachable!("floats have no exact value"),
+        }
+    }
+
+    fn is_float(&self) -> bool {
+        matches!(self, Number::Float(_))
+    }
+
+    fn is_zero(&self) -> bool {
+        match self {
+            Number::Int(i) => *i == 0,
+            Number::Big(i) => i.is_zero(),
+            Number::Ratio(r) => r.is_zero(),
+            Number::Float(f) => *f == 0.0,
+        }
+    }
+}
+
+impl From<Number> for Data {
+    fn from(number: Number) -> Data {
+        match number {
+            Number::Int(i) => Data::Int(i),
+            Number::Big(i) => Data::from_bigint(i),
+            Number::Ratio(r) => Data::from_rational(r),
+            Number::Float(f) => Data::Float(f),
+        }
+    }
+}
+
+type Operation = fn(Number, Number) -> Result<Number, Error>;
+
+pub fn load(interpreter: &mut Interpreter) {
+    interpreter.define_native("+", Arity::AtLeast(0), |_, args| {
+        fold("+", Number::Int(0), args, add)
+    });
+
+    interpreter.define_native("*", Arity::AtLeast(0), |_, args| {
+        fold("*", Number::Int(1), args, multiply)
+    });
+
+    interpreter.define_native("-", Arity::AtLeast(1), |_, args| {
+        let first = Number::from_data("-", &args[0])?;
+        if args.len() == 1 {
+            subtract(Number::Int(0), first).map(Data::from)
+        } else {
+            fold("-", first, &args[1..], subtract)
+        }
+    });
+
+    interpreter.define_native("/", Arity::AtLeast(1), |_, args| {
+        let first = Number::from_data("/", &args[0])?;
+        if args.len() == 1 {
+            divide(Number::Int(1), first).map(Data::from)
+        } else {
+            fold("/", first, &args[1..], divide)
+        }
+    });
+
+    interpreter.define_native("quotient", Arity::Exactly(2), |_, args| {
+        integer_division("quotient", args, i64::checked_div, |x, y| x / y)
+    });
+
+    interpreter.define_native("remainder", Arity::Exactly(2), |_, args| {
+        integer_division("remainder", args, i64::checked_rem, |x, y| x % y)
+    });
+
+    interpreter.define_native("modulo", Arity::Exactly(2), |_, args| {
+        // Like the remainder, but with the sign of the divisor
+        integer_division(
+            "modulo",
+            args,
+            |x, y| {
+                let r = x.checked_rem(y)?;
+                if r != 0 && (r < 0) != (y < 0) {
+                    r.checked_add(y)
+                } else {
+                    Some(r)
+                }
+            },
+            Integer::mod_floor,
+        )
+    });
+
+    interpreter.define_native("abs", Arity::Exactly(1), |_, args| {
+        Ok(match Number::from_data("abs", &args[0])? {
+            Number::Int(i) => match i.checked_abs() {
+                Some(i) => Data::Int(i),
+                None => Data::from_bigint(BigInt::from(i).abs()),
+            },
+            Number::Big(i) => Data::from_bigint(i.abs()),
+            Number::Ratio(r) => Data::from_rational(r.abs()),
+            Number::Float(f) => Data::Float(f.abs()),
+        })
+    });
+
+    interpreter.define_native("min", Arity::AtLeast(1), |_, args| {
+        extremum("min", args, Ordering::Less)
+    });
+
+    interpreter.define_native("max", Arity::AtLeast(1), |_, args| {
+        extremum("max", args, Ordering::Greater)
+    });
+
+    interpreter.define_native("=", Arity::AtLeast(1), |_, args| {
+        compare_chain("=", args, |ordering| ordering == Ordering::Equal)
+    });
+
+    interpreter.define_native("<", Arity::AtLeast(1), |_, args| {
+        compare_chain("<", args, |ordering| ordering == Ordering::Less)
+    });
+
+    interpreter.define_native(">", Arity::AtLeast(1), |_, args| {
+        compare_chain(">", args, |ordering| ordering == Ordering::Greater)
+    });
+
+    interpreter.define_native("<=", Arity::AtLeast(1), |_, args| {
+        compare_chain("<=", args, |ordering| ordering != Ordering::Greater)
+    });
+
+    interpreter.define_native(">=", Arity::AtLeast(1), |_, args| {
+        compare_chain(">=", args, |ordering| ordering != Ordering::Less)
+    });
+
+    interpreter.define_native("zero?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(Number::from_data("zero?", &args[0])?.is_zero()))
+    });
+
+    interpreter.define_native("even?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(integer("even?", &args[0])?.is_even()))
+    });
+
+    interpreter.define_native("odd?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(integer("odd?", &args[0])?.is_odd()))
+    });
+
+    interpreter.define_native("number?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(Number::from_data("number?", &args[0]).is_ok()))
+    });
+
+    interpreter.define_native("integer?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(match &args[0] {
+            Data::Int(_) | Data::BigInt(_) => true,
+            Data::Float(f) => f.fract() == 0.0,
+            _ => false,
+        }))
+    });
+
+    interpreter.define_native("rational?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(match &args[0] {
+            Data::Int(_) | Data::BigInt(_) | Data::Rational(_) => true,
+            Data::Float(f) => f.is_finite(),
+            _ => false,
+        }))
+    });
+
+    interpreter.define_native("exact?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(
+            !Number::from_data("exact?", &args[0])?.is_float(),
+        ))
+    });
+
+    interpreter.define_native("inexact?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(
+            Number::from_data("inexact?", &args[0])?.is_float(),
+        ))
+    });
+
+    interpreter.define_native("exact->inexact", Arity::Exactly(1), |_, args| {
+        Ok(Data::Float(
+            Number::from_data("exact->inexact", &args[0])?.to_f64(),
+        ))
+    });
+
+    interpreter.define_native("inexact->exact", Arity::Exactly(1), |_, args| {
+        exact("inexact->exact", &args[0]).map(Data::from)
+    });
+
+    interpreter.define_native("numerator", Arity::Exactly(1), |_, args| {
+        fraction_part("numerator", &args[0], |r| r.numer().clone())
+    });
+
+    interpreter.define_native("denominator", Arity::Exactly(1), |_, args| {
+        fraction_part("denominator", &args[0], |r| r.denom().clone())
+    });
+
+    // (iota count start step) lists `count` numbers, from `start` (0 by
+    // default) on, counting by `step` (1 by default)
+    interpreter.define_native("iota", Arity::Between(1, 3), |_, args| {
+        let count = match &args[0] {
+            Data::Int(count) if *count >= 0 => *count,
+            x => return Err(wrong_type("iota", x)),
+        };
+        let start = optional_number("iota", args.get(1), 0)?;
+        let step = optional_number("iota", args.get(2), 1)?;
+
+        let mut items = Vec::new();
+        for i in 0..count {
+            items.push(nth_step(&start, &step, i)?.into());
+        }
+        Ok(Data::list(items))
+    });
+
+    // (range end), (range start end) or (range start end step) lists the
+    // numbers from `start` (0 by default) up to `end`, which is left out,
+    // counting by `step` (1 by default, and can be negative)
+    interpreter.define_native("range", Arity::Between(1, 3), |_, args| {
+        let (start, end) = match args {
+            [end] => (Number::Int(0), Number::from_data("range", end)?),
+            [start, end, ..] => (
+                Number::from_data("range", start)?,
+                Number::from_data("range", end)?,
+            ),
+            _ => unreachable!(),
+        };
+        let step = optional_number("range", args.get(2), 1)?;
+        let direction = match compare(&step, &Number::Int(0)) {
+            Some(Ordering::Greater) => Ordering::Less,
+            Some(Ordering::Less) => Ordering::Greater,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::Value,
+                    format!("range: invalid step {}", args[2].repr()),
+                ))
+            }
+        };
+
+        let mut items = Vec::new();
+        for i in 0.. {
+            let number = nth_step(&start, &step, i)?;
+            if compare(&number, &end) != Some(direction) {
+                break;
+            }
+            items.push(number.into());
+        }
+        Ok(Data::list(items))
+    });
+}
+
+/// An optional number argument, which is `default` if it's missing.
+fn optional_number(function: &str, arg: Option<&Data>, default: i64) -> Result<Number, Error> {
+    match arg {
+        Some(arg) => Number::from_data(function, arg),
+        None => Ok(Number::Int(default)),
+    }
+}
+
+/// The `i`th number of a sequence going from `start` by `step`, computed
+/// directly so floats don't accumulate rounding errors.
+fn nth_step(start: &Number, step: &Number, i: i64) -> Result<Number, Error> {
+    add(start.clone(), multiply(Number::Int(i), step.clone())?)
+}
+
+fn integer(function: &str, data: &Data) -> Result<BigInt, Error> {
+    match data {
+        Data::Int(i) => Ok((*i).into()),
+        Data::BigInt(i) => Ok(i.clone()),
+        x => Err(wrong_type(function, x)),
+    }
+}
+
+/// Converts a number to an exact one. Floats are converted to the exact
+/// fraction they represent.
+fn exact(function: &str, data: &Data) -> Result<Number, Error> {
+    match Number::from_data(function, data)? {
+        Number::Float(f) => match BigRational::from_float(f) {
+            Some(r) => Ok(Number::from_rational(r)),
+            None => Err(Error::new(
+                ErrorKind::Value,
+                format!("{}: {} has no exact representation", function, data.repr()),
+            )),
+        },
+        number => Ok(number),
+    }
+}
+
+/// Gets the numerator or denominator of a number. For floats, that's the
+/// one of the fraction they represent, converted back to a float.
+fn fraction_part(function: &str, data: &Data, part: fn(&BigRational) -> BigInt) -> EvalResult {
+    let value = part(&exact(function, data)?.to_rational());
+    if let Data::Float(_) = data {
+        Ok(Data::Float(value.to_f64().unwrap_or(f64::NAN)))
+    } else {
+        Ok(Data::from_bigint(value))
+    }
+}
+
+fn division_by_zero(function: &str) -> Error {
+    Error::new(ErrorKind::Value, format!("{}: division by zero", function))
+}
+
+/// Applies `operation` to `initial` and each argument in turn.
+fn fold(function: &str, initial: Number, args: &[Data], operation: Operation) -> EvalResult {
+    let mut current = initial;
+    for arg in args {
+        current = operation(current, Number::from_data(function, arg)?)?;
+    }
+    Ok(current.into())
+}
+
+/// Applies an operation to two numbers. `int_op` is tried first if both
+/// are `i64`s, and returns `None` on overflow, in which case `exact_op` is
+/// used. `float_op` is used if either of them is a float.
+fn combine(
+    a: Number,
+    b: Number,
+    int_op: fn(i64, i64) -> Option<i64>,
+    exact_op: fn(BigRational, BigRational) -> BigRational,
+    float_op: fn(f64, f64) -> f64,
+) -> Number {
+    if let (Number::Int(x), Number::Int(y)) = (&a, &b) {
+        if let Some(result) = int_op(*x, *y) {
+            return Number::Int(result);
+        }
+    }
+
+    if a.is_float() || b.is_float() {
+        Number::Float(float_op(a.to_f64(), b.to_f64()))
+    } else {
+        Number::from_rational(exact_op(a.to_rational(), b.to_rational()))
+    }
+}
+
+fn add(a: Number, b: Number) -> Result<Number, Error> {
+    Ok(combine(a, b, i64::checked_add, |x, y| x + y, |x, y| x + y))
+}
+
+fn subtract(a: Number, b: Number) -> Result<Number, Error> {
+    Ok(combine(a, b, i64::checked_sub, |x, y| x - y, |x, y| x - y))
+}
+
+fn multiply(a: Number, b: Number) -> Result<Number, Error> {
+    Ok(combine(a, b, i64::checked_mul, |x, y| x * y, |x, y| x * y))
+}
+
+/// Divides two numbers. Dividing by an exact zero is an error, while
+/// dividing by a float zero gives an infinity (or NaN).
+fn divide(a: Number, b: Number) -> Result<Number, Error> {
+    if b.is_zero() && !b.is_float() {
+        return Err(division_by_zero("/"));
+    }
+
+    Ok(combine(
+        a,
+        b,
+        |x, y| match x.checked_rem(y)? {
+            0 => x.checked_div(y),
+            _ => None,
+        },
+        |x, y| x / y,
+        |x, y| x / y,
+    ))
+}
+
+/// Applies an operation on two integers, where the second one can't be
+/// zero. `int_op` is tried first if both are `i64`s, and returns `None` on
+/// overflow, in which case `big_op` is used.
+fn integer_division(
+    function: &str,
+    args: &[Data],
+    int_op: fn(i64, i64) -> Option<i64>,
+    big_op: fn(&BigInt, &BigInt) -> BigInt,
+) -> EvalResult {
+    let x = integer(function, &args[0])?;
+    let y = integer(function, &args[1])?;
+    if y.is_zero() {
+        return Err(division_by_zero(function));
+    }
+
+    if let (Data::Int(x), Data::Int(y)) = (&args[0], &args[1]) {
+        if let Some(result) = int_op(*x, *y) {
+            return Ok(Data::Int(result));
+        }
+    }
+    Ok(Data::from_bigint(big_op(&x, &y)))
+}
+
+/// Compares two numbers. Returns `None` if either of them is NaN.
+fn compare(a: &Number, b: &Number) -> Option<Ordering> {
+    match (a, b) {
+        (Number::Int(x), Number::Int(y)) => Some(x.cmp(y)),
+        _ if a.is_float() || b.is_float() => a.to_f64().partial_cmp(&b.to_f64()),
+        _ => Some(a.to_rational().cmp(&b.to_rational())),
+    }
+}
+
+/// Checks that `test` holds for every pair of adjacent arguments.
+fn compare_chain(function: &str, args: &[Data], test: fn(Ordering) -> bool) -> EvalResult {
+    let numbers = args
+        .iter()
+        .map(|arg| Number::from_data(function, arg))
+        .collect::<Result<Vec<Number>, Error>>()?;
+
+    Ok(Data::Bool(
+        numbers
+            .windows(2)
+            .all(|pair| compare(&pair[0], &pair[1]).is_some_and(test)),
+    ))
+}
+
+/// Finds the argument that compares as `wanted` to all others. The result
+/// is a float if any of the arguments is a float.
+fn extremum(function: &str, args: &[Data], wanted: Ordering) -> EvalResult {
+    let mut result = Number::from_data(function, &args[0])?;
+    let mut any_float = result.is_float();
+    for arg in &args[1..] {
+        let number = Number::from_data(function, arg)?;
+        any_float |= number.is_float();
+        if compare(&number, &result) == Some(wanted) {
+            result = number;
+        }
+    }
+
+    if any_float {
+        Ok(Data::Float(result.to_f64()))
+    } else {
+        Ok(result.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::symbol::Symbol;
+
+    /// Calls the arithmetic function `name` and writes its result.
+    fn call(name: &str, args: &[Data]) -> Result<String, Error> {
+        let mut interpreter = Interpreter::new(Vec::new());
+        let function = interpreter.eval(Ok(Data::Symbol(Symbol::intern(name))))?;
+        interpreter
+            .apply(&function, args)
+            .map(|result| result.repr())
+    }
+
+    #[test]
+    fn integers_and_floats() {
+        assert_eq!(call("+", &[Data::Int(1), Data::Int(2)]).unwrap(), "3");
+        assert_eq!(call("+", &[Data::Int(1), Data::Float(0.5)]).unwrap(), "1.5");
+        assert_eq!(
+            call("min", &[Data::Int(1), Data::Float(2.0)]).unwrap(),
+            "1.0"
+        );
+        assert_eq!(call("-", &[Data::Int(5)]).unwrap(), "-5");
+    }
+
+    #[test]
+    fn integer_division() {
+        let (a, b) = (Data::Int(-7), Data::Int(2));
+        assert_eq!(call("quotient", &[a.clone(), b.clone()]).unwrap(), "-3");
+        assert_eq!(call("remainder", &[a.clone(), b.clone()]).unwrap(), "-1");
+        assert_eq!(call("modulo", &[a, b]).unwrap(), "1");
+    }
+
+    #[test]
+    fn division_by_exact_zero() {
+        for function in ["/", "quotient", "remainder", "modulo"].iter() {
+            let error = call(function, &[Data::Int(1), Data::Int(0)]).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::Value, "{}", function);
+        }
+    }
+
+    #[test]
+    fn division_by_inexact_zero() {
+        assert_eq!(
+            call("/", &[Data::Int(1), Data::Float(0.0)]).unwrap(),
+            "+inf.0"
+        );
+    }
+
+    #[test]
+    fn promotes_to_big_integers() {
+        let max = Data::Int(i64::MAX);
+        assert_eq!(
+            call("+", &[max.clone(), Data::Int(1)]).unwrap(),
+            "9223372036854775808"
+        );
+        assert_eq!(
+            call("*", &[max, Data::Int(2)]).unwrap(),
+            "18446744073709551614"
+        );
+        assert_eq!(
+            call("-", &[Data::Int(i64::MIN)]).unwrap(),
+            "9223372036854775808"
+        );
+    }
+
+    #[test]
+    fn demotes_small_results() {
+        let big = Data::from_bigint(BigInt::from(i64::MAX) + 1);
+        let result = call("-", &[big, Data::Int(1)]).unwrap();
+        assert_eq!(result, i64::MAX.to_string());
+    }
+
+    #[test]
+    fn exact_fractions() {
+        assert_eq!(call("/", &[Data::Int(1), Data::Int(3)]).unwrap(), "1/3");
+        assert_eq!(call("/", &[Data::Int(6), Data::Int(3)]).unwrap(), "2");
+        assert_eq!(call("/", &[Data::Int(4)]).unwrap(), "1/4");
+        let half = Data::from_rational(BigRational::new(1.into(), 2.into()));
+        assert_eq!(call("+", &[half.clone(), half.clone()]).unwrap(), "1");
+        assert_eq!(call("denominator", &[half]).unwrap(), "2");
+    }
+
+    #[test]
+    fn exactness_conversions() {
+        let third = Data::from_rational(BigRational::new(1.into(), 3.into()));
+        assert_eq!(
+            call("exact->inexact", &[third]).unwrap(),
+            "0.3333333333333333"
+        );
+        assert_eq!(call("inexact->exact", &[Data::Float(0.5)]).unwrap(), "1/2");
+    }
+
+    #[test]
+    fn comparisons() {
+        assert_eq!(
+            call("<", &[Data::Int(1), Data::Float(1.5), Data::Int(2)]).unwrap(),
+            "#t"
+        );
+        assert_eq!(
+            call(">=", &[Data::Int(2), Data::Int(2), Data::Int(3)]).unwrap(),
+            "#f"
+        );
+        assert_eq!(
+            call("=", &[Data::Int(1), Data::Float(f64::NAN)]).unwrap(),
+            "#f"
+        );
+        assert!(call("<", &[Data::Int(1), Data::Str("2".into())]).is_err());
+    }
+}
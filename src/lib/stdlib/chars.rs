@@ -0,0 +1,114 @@
+//! Functions on characters.
+
+use super::{compare_chain, wrong_type};
+use crate::lib::data::{Arity, Data};
+use crate::lib::error::{Error, ErrorKind};
+use crate::lib::interpreter::Interpreter;
+use std::cmp::Ordering;
+
+pub fn load(interpreter: &mut Interpreter) {
+    interpreter.define_native("char->integer", Arity::Exactly(1), |_, args| {
+        Ok(Data::Int(character("char->integer", &args[0])? as i64))
+    });
+
+    interpreter.define_native("integer->char", Arity::Exactly(1), |_, args| {
+        match &args[0] {
+            Data::Int(i) => std::char::from_u32(*i as u32)
+                .filter(|_| 0 <= *i && *i <= u32::MAX as i64)
+                .map(Data::Char)
+                .ok_or_else(|| {
+                    Error::new(
+                        ErrorKind::Value,
+                        format!("integer->char: {} is not a valid character code", i),
+                    )
+                }),
+            x => Err(wrong_type("integer->char", x)),
+        }
+    });
+
+    interpreter.define_native("char-upcase", Arity::Exactly(1), |_, args| {
+        let c = character("char-upcase", &args[0])?;
+        // Characters like 'ß' become more than one character, keep those
+        let mut upper = c.to_uppercase();
+        Ok(Data::Char(match (upper.next(), upper.next()) {
+            (Some(u), None) => u,
+            _ => c,
+        }))
+    });
+
+    interpreter.define_native("char-downcase", Arity::Exactly(1), |_, args| {
+        let c = character("char-downcase", &args[0])?;
+        let mut lower = c.to_lowercase();
+        Ok(Data::Char(match (lower.next(), lower.next()) {
+            (Some(l), None) => l,
+            _ => c,
+        }))
+    });
+
+    interpreter.define_native("char-alphabetic?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(
+            character("char-alphabetic?", &args[0])?.is_alphabetic(),
+        ))
+    });
+
+    interpreter.define_native("char-numeric?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(
+            character("char-numeric?", &args[0])?.is_numeric(),
+        ))
+    });
+
+    interpreter.define_native("char-whitespace?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(
+            character("char-whitespace?", &args[0])?.is_whitespace(),
+        ))
+    });
+
+    interpreter.define_native("char-upper-case?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(
+            character("char-upper-case?", &args[0])?.is_uppercase(),
+        ))
+    });
+
+    interpreter.define_native("char-lower-case?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(
+            character("char-lower-case?", &args[0])?.is_lowercase(),
+        ))
+    });
+
+    interpreter.define_native("char=?", Arity::AtLeast(1), |_, args| {
+        compare_chain("char=?", args, character, |ordering| {
+            ordering == Ordering::Equal
+        })
+    });
+
+    interpreter.define_native("char<?", Arity::AtLeast(1), |_, args| {
+        compare_chain("char<?", args, character, |ordering| {
+            ordering == Ordering::Less
+        })
+    });
+
+    interpreter.define_native("char>?", Arity::AtLeast(1), |_, args| {
+        compare_chain("char>?", args, character, |ordering| {
+            ordering == Ordering::Greater
+        })
+    });
+
+    interpreter.define_native("char<=?", Arity::AtLeast(1), |_, args| {
+        compare_chain("char<=?", args, character, |ordering| {
+            ordering != Ordering::Greater
+        })
+    });
+
+    interpreter.define_native("char>=?", Arity::AtLeast(1), |_, args| {
+        compare_chain("char>=?", args, character, |ordering| {
+            ordering != Ordering::Less
+        })
+    });
+}
+
+fn character(function: &str, data: &Data) -> Result<char, Error> {
+    match data {
+        Data::Char(c) => Ok(*c),
+        x => Err(wrong_type(function, x)),
+    }
+}
@@ -0,0 +1,60 @@
+//! Raising and handling errors. `guard` and `try` are special forms, in
+//! the interpreter.
+
+use super::wrong_type;
+use crate::lib::data::{Arity, Data};
+use crate::lib::error::{Error, ErrorKind};
+use crate::lib::interpreter::Interpreter;
+use std::rc::Rc;
+
+pub fn load(interpreter: &mut Interpreter) {
+    interpreter.define_native("error", Arity::AtLeast(1), |interpreter, args| {
+        let message = match &args[0] {
+            Data::Str(s) => s.to_string(),
+            x => return Err(wrong_type("error", x)),
+        };
+        let error = Error::new(ErrorKind::User, message).with_irritants(args[1..].to_vec());
+        Err(interpreter.raise(error))
+    });
+
+    interpreter.define_native("raise", Arity::Exactly(1), |interpreter, args| {
+        Err(interpreter.raise(Error::raised(args[0].clone())))
+    });
+
+    interpreter.define_native(
+        "raise-continuable",
+        Arity::Exactly(1),
+        |interpreter, args| interpreter.raise_continuable(args[0].clone()),
+    );
+
+    interpreter.define_native(
+        "with-exception-handler",
+        Arity::Exactly(2),
+        |interpreter, args| interpreter.with_exception_handler(&args[0], &args[1]),
+    );
+
+    interpreter.define_native("error-object?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(matches!(args[0], Data::Error(_))))
+    });
+
+    interpreter.define_native("error-object-message", Arity::Exactly(1), |_, args| {
+        Ok(Data::Str(
+            error_object("error-object-message", &args[0])?
+                .message
+                .as_str()
+                .into(),
+        ))
+    });
+
+    interpreter.define_native("error-object-irritants", Arity::Exactly(1), |_, args| {
+        let error = error_object("error-object-irritants", &args[0])?;
+        Ok(Data::list(error.irritants.clone()))
+    });
+}
+
+fn error_object(function: &str, data: &Data) -> Result<Rc<Error>, Error> {
+    match data {
+        Data::Error(error) => Ok(error.clone()),
+        x => Err(wrong_type(function, x)),
+    }
+}
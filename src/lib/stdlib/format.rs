@@ -0,0 +1,348 @@
+//! `format`, which builds a string out of a template with directives.
+//!
+//! The directives are a subset of Common Lisp's. Each one starts with a
+//! `~`, then optional parameters separated by commas (numbers, or
+//! characters quoted like `'0`), optional `@` and `:` modifiers and a
+//! letter:
+//!
+//! - `~a` displays an argument as `print` would, and `~s` writes it as
+//!   the REPL would. `~10a` pads it with spaces on the right up to 10
+//!   characters, `~10@a` on the left, and `~10,'.a` with dots.
+//! - `~d`, `~b`, `~o` and `~x` write an integer in base 10, 2, 8 and 16,
+//!   padded on the left as in `~5,'0d`. With `@`, positive numbers get a
+//!   `+` sign.
+//! - `~f` writes a number as a float, as in `~,2f` for 2 decimals or
+//!   `~8,2f` to also pad it to 8 characters.
+//! - `~%` is a newline and `~~` is a tilde.
+//! - `~{...~}` takes a list argument and formats the text inside for its
+//!   items, as many at a time as that text uses, until there are none
+//!   left. In there, `~^` stops when there are no more items, as in
+//!   `(format "~{~a~^, ~}" '(1 2 3))` => "1, 2, 3".
+
+use super::wrong_type;
+use crate::lib::data::{Arity, Data};
+use crate::lib::error::{Error, ErrorKind};
+use crate::lib::interpreter::Interpreter;
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+use std::iter::Peekable;
+use std::slice;
+use std::str::Chars;
+
+pub fn load(interpreter: &mut Interpreter) {
+    interpreter.define_native("format", Arity::AtLeast(1), |_, args| {
+        let template = match &args[0] {
+            Data::Str(template) => template.clone(),
+            x => return Err(wrong_type("format", x)),
+        };
+        let pieces = parse(&mut template.chars().peekable(), false)?;
+        let mut result = String::new();
+        run(&pieces, &mut args[1..].iter(), &mut result)?;
+        Ok(Data::Str(result.into()))
+    });
+}
+
+/// A parsed part of a template.
+enum Piece {
+    Text(String),
+    Directive(Directive),
+    /// `~{...~}`
+    Iteration(Vec<Piece>),
+    /// `~^`
+    Stop,
+}
+
+struct Directive {
+    letter: char,
+    params: Vec<Option<Param>>,
+    /// Whether the `@` modifier was given.
+    at: bool,
+}
+
+enum Param {
+    Number(i64),
+    Char(char),
+}
+
+/// The largest number a directive parameter can be. Widths, decimals and
+/// newline counts bigger than that would only build huge strings.
+const MAX_PARAMETER: i64 = 10_000;
+
+impl Directive {
+    /// The number given as the `index`th parameter, if any.
+    fn number(&self, index: usize) -> Result<Option<usize>, Error> {
+        match self.params.get(index) {
+            Some(Some(Param::Number(n))) if (0..=MAX_PARAMETER).contains(n) => {
+                Ok(Some(*n as usize))
+            }
+            Some(Some(_)) => Err(format_error(&format!(
+                "invalid parameter for ~{}",
+                self.letter
+            ))),
+            _ => Ok(None),
+        }
+    }
+
+    /// The character given as the `index`th parameter, or a space.
+    fn pad_char(&self, index: usize) -> Result<char, Error> {
+        match self.params.get(index) {
+            Some(Some(Param::Char(c))) => Ok(*c),
+            Some(Some(_)) => Err(format_error(&format!(
+                "invalid parameter for ~{}",
+                self.letter
+            ))),
+            _ => Ok(' '),
+        }
+    }
+}
+
+/// Parses a template up to its end, or up to the `~}` that closes the
+/// iteration it's in.
+fn parse(chars: &mut Peekable<Chars>, in_iteration: bool) -> Result<Vec<Piece>, Error> {
+    let mut pieces = Vec::new();
+    let mut text = String::new();
+    loop {
+        match chars.next() {
+            Some('~') => (),
+            Some(c) => {
+                text.push(c);
+                continue;
+            }
+            None if in_iteration => return Err(format_error("unterminated ~{")),
+            None => break,
+        }
+
+        let mut params = Vec::new();
+        loop {
+            params.push(parse_param(chars)?);
+            if chars.peek() != Some(&',') {
+                break;
+            }
+            chars.next();
+        }
+        let mut at = false;
+        while let Some(&modifier @ ('@' | ':')) = chars.peek() {
+            at |= modifier == '@';
+            chars.next();
+        }
+
+        let letter = match chars.next() {
+            Some(letter) => letter.to_ascii_lowercase(),
+            None => return Err(format_error("template ends in the middle of a directive")),
+        };
+        let directive = Directive { letter, params, at };
+        let piece = match letter {
+            '~' => {
+                text.push('~');
+                continue;
+            }
+            '%' => {
+                let count = directive.number(0)?.unwrap_or(1);
+                text.push_str(&"\n".repeat(count));
+                continue;
+            }
+            '{' => Piece::Iteration(parse(chars, true)?),
+            '}' if in_iteration => break,
+            '^' => Piece::Stop,
+            'a' | 's' | 'd' | 'b' | 'o' | 'x' | 'f' => Piece::Directive(directive),
+            _ => return Err(format_error(&format!("unknown directive ~{}", letter))),
+        };
+        if !text.is_empty() {
+            pieces.push(Piece::Text(std::mem::take(&mut text)));
+        }
+        pieces.push(piece);
+    }
+
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+/// Parses one (possibly missing) directive parameter.
+fn parse_param(chars: &mut Peekable<Chars>) -> Result<Option<Param>, Error> {
+    match chars.peek() {
+        Some('\'') => {
+            chars.next();
+            match chars.next() {
+                Some(c) => Ok(Some(Param::Char(c))),
+                None => Err(format_error("template ends in the middle of a directive")),
+            }
+        }
+        Some(c) if c.is_ascii_digit() || *c == '-' => {
+            let mut digits = chars.next().unwrap().to_string();
+            while let Some(&digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                digits.push(digit);
+                chars.next();
+            }
+            match digits.parse() {
+                Ok(n) => Ok(Some(Param::Number(n))),
+                Err(_) => Err(format_error(&format!("invalid parameter {}", digits))),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Formats `pieces` into `out`, taking the arguments they use from `args`.
+/// Returns false if it was stopped by a `~^`.
+fn run(pieces: &[Piece], args: &mut slice::Iter<Data>, out: &mut String) -> Result<bool, Error> {
+    for piece in pieces {
+        match piece {
+            Piece::Text(text) => out.push_str(text),
+            Piece::Directive(directive) => {
+                let arg = args
+                    .next()
+                    .ok_or_else(|| format_error("not enough arguments"))?;
+                out.push_str(&format_directive(directive, arg)?);
+            }
+            Piece::Iteration(body) => {
+                let arg = args
+                    .next()
+                    .ok_or_else(|| format_error("not enough arguments"))?;
+                let items = arg.list_items().ok_or_else(|| wrong_type("format", arg))?;
+                let mut items = items.iter();
+                while items.len() > 0 {
+                    let left = items.len();
+                    // A body that doesn't use any item would never end
+                    if !run(body, &mut items, out)? || items.len() == left {
+                        break;
+                    }
+                }
+            }
+            Piece::Stop if args.len() == 0 => return Ok(false),
+            Piece::Stop => (),
+        }
+    }
+    Ok(true)
+}
+
+fn format_directive(directive: &Directive, arg: &Data) -> Result<String, Error> {
+    let (text, pad_left) = match directive.letter {
+        'a' => (arg.to_lisp_string(), directive.at),
+        's' => (arg.repr(), directive.at),
+        'd' => (integer(arg, 10, directive.at), true),
+        'b' => (integer(arg, 2, directive.at), true),
+        'o' => (integer(arg, 8, directive.at), true),
+        'x' => (integer(arg, 16, directive.at), true),
+        'f' => (float(arg, directive.number(1)?), true),
+        letter => unreachable!("format directive: {:?}", letter),
+    };
+    let width = directive.number(0)?.unwrap_or(0);
+    let pad_char = directive.pad_char(if directive.letter == 'f' { 2 } else { 1 })?;
+    Ok(pad(text, width, pad_char, pad_left))
+}
+
+/// Writes an integer in base `radix`. Other values are displayed as they
+/// are.
+fn integer(arg: &Data, radix: u32, sign: bool) -> String {
+    let i = match arg {
+        Data::Int(i) => BigInt::from(*i),
+        Data::BigInt(i) => i.clone(),
+        x => return x.to_lisp_string(),
+    };
+    let written = i.to_str_radix(radix);
+    if sign && i >= BigInt::from(0) {
+        format!("+{}", written)
+    } else {
+        written
+    }
+}
+
+/// Writes a number as a float, with `decimals` digits after the point if
+/// given. Other values are displayed as they are.
+fn float(arg: &Data, decimals: Option<usize>) -> String {
+    let f = match arg {
+        Data::Int(i) => *i as f64,
+        Data::BigInt(i) => i.to_f64().unwrap_or(f64::NAN),
+        Data::Rational(r) => r.to_f64().unwrap_or(f64::NAN),
+        Data::Float(f) => *f,
+        x => return x.to_lisp_string(),
+    };
+    match decimals {
+        Some(decimals) if f.is_finite() => format!("{:.*}", decimals, f),
+        _ => Data::Float(f).repr(),
+    }
+}
+
+/// Pads `text` with `pad_char` up to `width` characters, on the left or
+/// on the right.
+fn pad(text: String, width: usize, pad_char: char, left: bool) -> String {
+    let length = text.chars().count();
+    if length >= width {
+        return text;
+    }
+    let padding = pad_char.to_string().repeat(width - length);
+    if left {
+        padding + &text
+    } else {
+        text + &padding
+    }
+}
+
+fn format_error(message: &str) -> Error {
+    Error::new(ErrorKind::Value, format!("format: {}", message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(template: &str, args: &[Data]) -> Result<String, Error> {
+        let pieces = parse(&mut template.chars().peekable(), false)?;
+        let mut result = String::new();
+        run(&pieces, &mut args.iter(), &mut result)?;
+        Ok(result)
+    }
+
+    #[test]
+    fn directives() {
+        let args = [Data::Str("a".into()), Data::Str("a".into())];
+        assert_eq!(format("~a ~s~%", &args).unwrap(), "a \"a\"\n");
+        assert_eq!(
+            format("~5,'0d|~x", &[Data::Int(42), Data::Int(255)]).unwrap(),
+            "00042|ff"
+        );
+        assert_eq!(
+            format("~,2f ~@d", &[Data::Float(1.5), Data::Int(1)]).unwrap(),
+            "1.50 +1"
+        );
+        assert_eq!(
+            format("~3a|~3@a", &[Data::Int(1), Data::Int(2)]).unwrap(),
+            "1  |  2"
+        );
+    }
+
+    #[test]
+    fn iteration() {
+        let list = Data::list(vec![Data::Int(1), Data::Int(2), Data::Int(3)]);
+        assert_eq!(format("~{~a~^, ~}", &[list]).unwrap(), "1, 2, 3");
+        let empty = Data::list(Vec::new());
+        assert_eq!(format("~{~}", &[empty]).unwrap(), "");
+    }
+
+    #[test]
+    fn invalid_parameters() {
+        for template in [
+            "~-1%",
+            "~-5a",
+            "~9999999999999a",
+            "~,100000f",
+            "~99999999999999999999a",
+        ]
+        .iter()
+        {
+            let error = format(template, &[Data::Float(1.0)]).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::Value, "{}", template);
+        }
+        assert_eq!(format("~2%", &[]).unwrap(), "\n\n");
+    }
+
+    #[test]
+    fn malformed_templates() {
+        assert!(format("~a", &[]).is_err());
+        assert!(format("~q", &[]).is_err());
+        assert!(format("~{~a", &[]).is_err());
+        assert!(format("~", &[]).is_err());
+    }
+}
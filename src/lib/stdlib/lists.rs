@@ -0,0 +1,325 @@
+//! Pairs and lists.
+
+use super::{out_of_range, wrong_type};
+use crate::lib::data::{Arity, Data, Pair};
+use crate::lib::error::{Error, ErrorKind};
+use crate::lib::interpreter::{EvalResult, Interpreter};
+use std::rc::Rc;
+
+pub fn load(interpreter: &mut Interpreter) {
+    interpreter.define_native("cons", Arity::Exactly(2), |_, args| {
+        Ok(Data::cons(args[0].clone(), args[1].clone()))
+    });
+
+    interpreter.define_native("car", Arity::Exactly(1), |_, args| {
+        args[0].car().ok_or_else(|| wrong_type("car", &args[0]))
+    });
+
+    interpreter.define_native("cdr", Arity::Exactly(1), |_, args| {
+        args[0].cdr().ok_or_else(|| wrong_type("cdr", &args[0]))
+    });
+
+    interpreter.define_native("set-car!", Arity::Exactly(2), |_, args| {
+        *pair("set-car!", &args[0])?.car.borrow_mut() = args[1].clone();
+        Ok(Data::Nil)
+    });
+
+    interpreter.define_native("set-cdr!", Arity::Exactly(2), |_, args| {
+        *pair("set-cdr!", &args[0])?.cdr.borrow_mut() = args[1].clone();
+        Ok(Data::Nil)
+    });
+
+    interpreter.define_native("list", Arity::AtLeast(0), |_, args| {
+        Ok(Data::list(args.to_vec()))
+    });
+
+    interpreter.define_native("length", Arity::Exactly(1), |_, args| {
+        Ok(Data::Int(items("length", &args[0])?.len() as i64))
+    });
+
+    // Copies all lists but the last one, which becomes the tail of the
+    // result (and doesn't have to be a list)
+    interpreter.define_native("append", Arity::AtLeast(0), |_, args| {
+        let (last, init) = match args.split_last() {
+            Some(split) => split,
+            None => return Ok(Data::List(Vec::new().into())),
+        };
+        let mut result = Vec::new();
+        for list in init {
+            result.extend(items("append", list)?);
+        }
+        Ok(Data::list_with_tail(result, last.clone()))
+    });
+
+    interpreter.define_native("reverse", Arity::Exactly(1), |_, args| {
+        let mut items = items("reverse", &args[0])?;
+        items.reverse();
+        Ok(Data::list(items))
+    });
+
+    interpreter.define_native("list-tail", Arity::Exactly(2), |_, args| {
+        list_tail("list-tail", &args[0], &args[1])
+    });
+
+    interpreter.define_native("list-ref", Arity::Exactly(2), |_, args| {
+        list_tail("list-ref", &args[0], &args[1])?
+            .car()
+            .ok_or_else(|| out_of_range("list-ref", args))
+    });
+
+    interpreter.define_native("last", Arity::Exactly(1), |_, args| {
+        items("last", &args[0])?
+            .pop()
+            .ok_or_else(|| wrong_type("last", &args[0]))
+    });
+
+    // (map f list...) calls f with the first items of the lists, then with
+    // the second ones... until the shortest list runs out
+    interpreter.define_native("map", Arity::AtLeast(2), |interpreter, args| {
+        let mut results = Vec::new();
+        for call_args in transpose("map", &args[1..])? {
+            results.push(interpreter.apply(&args[0], &call_args)?);
+        }
+        Ok(Data::list(results))
+    });
+
+    interpreter.define_native("for-each", Arity::AtLeast(2), |interpreter, args| {
+        for call_args in transpose("for-each", &args[1..])? {
+            interpreter.apply(&args[0], &call_args)?;
+        }
+        Ok(Data::Nil)
+    });
+
+    interpreter.define_native("filter", Arity::Exactly(2), |interpreter, args| {
+        let mut kept = Vec::new();
+        for item in items("filter", &args[1])? {
+            if interpreter
+                .apply(&args[0], std::slice::from_ref(&item))?
+                .is_truthy()
+            {
+                kept.push(item);
+            }
+        }
+        Ok(Data::list(kept))
+    });
+
+    // (reduce f initial list) combines the items with f, as in
+    // (f item3 (f item2 item1)), or returns initial if the list is empty
+    interpreter.define_native("reduce", Arity::Exactly(3), |interpreter, args| {
+        let mut items = items("reduce", &args[2])?.into_iter();
+        let mut result = match items.next() {
+            Some(first) => first,
+            None => return Ok(args[1].clone()),
+        };
+        for item in items {
+            result = interpreter.apply(&args[0], &[item, result])?;
+        }
+        Ok(result)
+    });
+
+    // (fold-left f initial list...) calls (f result items...) with the
+    // items of the lists from left to right, starting with initial as the
+    // result
+    interpreter.define_native("fold-left", Arity::AtLeast(3), |interpreter, args| {
+        let mut result = args[1].clone();
+        for items in transpose("fold-left", &args[2..])? {
+            let mut call_args = vec![result];
+            call_args.extend(items);
+            result = interpreter.apply(&args[0], &call_args)?;
+        }
+        Ok(result)
+    });
+
+    // (fold-right f initial list...) calls (f items... result) with the
+    // items of the lists from right to left
+    interpreter.define_native("fold-right", Arity::AtLeast(3), |interpreter, args| {
+        let mut result = args[1].clone();
+        for mut call_args in transpose("fold-right", &args[2..])?.into_iter().rev() {
+            call_args.push(result);
+            result = interpreter.apply(&args[0], &call_args)?;
+        }
+        Ok(result)
+    });
+
+    // (member x list) returns the part of the list starting at the first
+    // item equal? to x (or compared with the optional third argument), or
+    // #f if there's none
+    interpreter.define_native("member", Arity::Between(2, 3), |interpreter, args| {
+        find_tail(interpreter, "member", &args[1], |interpreter, item| {
+            same(interpreter, args.get(2), &args[0], item)
+        })
+    });
+
+    interpreter.define_native("memq", Arity::Exactly(2), |interpreter, args| {
+        find_tail(interpreter, "memq", &args[1], |_, item| {
+            Ok(args[0].is_eq(item))
+        })
+    });
+
+    // (assoc key alist) returns the first pair of the association list
+    // whose car is equal? to key (or compared with the optional third
+    // argument), or #f if there's none
+    interpreter.define_native("assoc", Arity::Between(2, 3), |interpreter, args| {
+        find_entry(interpreter, "assoc", &args[1], |interpreter, key| {
+            same(interpreter, args.get(2), &args[0], key)
+        })
+    });
+
+    interpreter.define_native("assq", Arity::Exactly(2), |interpreter, args| {
+        find_entry(interpreter, "assq", &args[1], |_, key| {
+            Ok(args[0].is_eq(key))
+        })
+    });
+
+    // (sort list less?) sorts a copy of the list, keeping the order of the
+    // items that aren't less than each other
+    interpreter.define_native("sort", Arity::Exactly(2), |interpreter, args| {
+        let items = items("sort", &args[0])?;
+        Ok(Data::list(merge_sort(interpreter, &args[1], items)?))
+    });
+}
+
+/// The items of a list argument, which has to be a proper list.
+fn items(function: &str, list: &Data) -> Result<Vec<Data>, Error> {
+    list.list_items().ok_or_else(|| wrong_type(function, list))
+}
+
+/// The items of `lists` grouped by position: the first items of all of
+/// them, then the second ones... up to the length of the shortest one.
+fn transpose(function: &str, lists: &[Data]) -> Result<Vec<Vec<Data>>, Error> {
+    let lists = lists
+        .iter()
+        .map(|list| items(function, list))
+        .collect::<Result<Vec<Vec<Data>>, Error>>()?;
+    let length = lists.iter().map(Vec::len).min().unwrap_or(0);
+    Ok((0..length)
+        .map(|i| lists.iter().map(|list| list[i].clone()).collect())
+        .collect())
+}
+
+/// What's left of `list` after skipping `k` items, sharing its structure.
+fn list_tail(function: &str, list: &Data, index: &Data) -> EvalResult {
+    let k = super::index(function, index, &[list.clone(), index.clone()])?;
+    let mut tail = list.clone();
+    for _ in 0..k {
+        tail = match tail.cdr() {
+            Some(cdr) => cdr,
+            None if tail.is_null() => {
+                return Err(out_of_range(function, &[list.clone(), index.clone()]))
+            }
+            None => return Err(wrong_type(function, list)),
+        };
+    }
+    Ok(tail)
+}
+
+/// Whether `a` and `b` are the same according to `compare`, a lisp
+/// predicate, or `equal?` if there's none.
+fn same(
+    interpreter: &mut Interpreter,
+    compare: Option<&Data>,
+    a: &Data,
+    b: &Data,
+) -> Result<bool, Error> {
+    match compare {
+        Some(compare) => Ok(interpreter
+            .apply(compare, &[a.clone(), b.clone()])?
+            .is_truthy()),
+        None => Ok(a.is_equal(b)),
+    }
+}
+
+/// The first tail of `list` whose car `matches`, or #f.
+fn find_tail<F>(
+    interpreter: &mut Interpreter,
+    function: &str,
+    list: &Data,
+    mut matches: F,
+) -> EvalResult
+where
+    F: FnMut(&mut Interpreter, &Data) -> Result<bool, Error>,
+{
+    // A circular list would be searched forever
+    if !list.is_list() {
+        return Err(wrong_type(function, list));
+    }
+    let mut tail = list.clone();
+    while let Some(item) = tail.car() {
+        if matches(interpreter, &item)? {
+            return Ok(tail);
+        }
+        tail = tail.cdr().unwrap();
+    }
+    if tail.is_null() {
+        Ok(Data::Bool(false))
+    } else {
+        Err(wrong_type(function, list))
+    }
+}
+
+/// The first pair of the association list `alist` whose key (its car)
+/// `matches`, or #f.
+fn find_entry<F>(
+    interpreter: &mut Interpreter,
+    function: &str,
+    alist: &Data,
+    mut matches: F,
+) -> EvalResult
+where
+    F: FnMut(&mut Interpreter, &Data) -> Result<bool, Error>,
+{
+    for entry in items(function, alist)? {
+        let key = entry.car().ok_or_else(|| wrong_type(function, &entry))?;
+        if matches(interpreter, &key)? {
+            return Ok(entry);
+        }
+    }
+    Ok(Data::Bool(false))
+}
+
+/// Sorts `items` with `less`, a lisp predicate. It's a merge sort written
+/// here because the predicate can fail, and be inconsistent, which the
+/// sorts of the standard library don't allow.
+fn merge_sort(
+    interpreter: &mut Interpreter,
+    less: &Data,
+    mut items: Vec<Data>,
+) -> Result<Vec<Data>, Error> {
+    if items.len() <= 1 {
+        return Ok(items);
+    }
+    let right = items.split_off(items.len() / 2);
+    let mut left = merge_sort(interpreter, less, items)?.into_iter().peekable();
+    let mut right = merge_sort(interpreter, less, right)?.into_iter().peekable();
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // Items from the right half only go first if they're strictly
+        // less, so that equal items stay in order
+        if interpreter
+            .apply(less, &[b.clone(), a.clone()])?
+            .is_truthy()
+        {
+            merged.extend(right.next());
+        } else {
+            merged.extend(left.next());
+        }
+    }
+    merged.extend(left);
+    merged.extend(right);
+    Ok(merged)
+}
+
+/// The pair a mutating function was given. Lists read from the source
+/// code are constants, so they can't be changed.
+fn pair(function: &str, arg: &Data) -> Result<Rc<Pair>, Error> {
+    match arg {
+        Data::Pair(pair) => Ok(pair.clone()),
+        Data::List(list) if !list.is_empty() => Err(Error::new(
+            ErrorKind::Type,
+            format!("{}: list literals can't be modified:", function),
+        )
+        .with_irritants(vec![arg.clone()])),
+        x => Err(wrong_type(function, x)),
+    }
+}
@@ -0,0 +1,123 @@
+//! The functions every interpreter starts with, implemented in Rust.
+
+use crate::lib::data::{Arity, Data};
+use crate::lib::error::{Error, ErrorKind};
+use crate::lib::interpreter::{EvalResult, Interpreter};
+use std::cmp::Ordering;
+
+mod arithmetic;
+mod chars;
+mod exceptions;
+mod format;
+mod lists;
+mod predicates;
+mod strings;
+mod symbols;
+mod vectors;
+
+/// Defines the whole standard library in the interpreter's global
+/// environment.
+pub fn load(interpreter: &mut Interpreter) {
+    interpreter.define("nil", Data::Nil);
+    interpreter.define("true", Data::Bool(true));
+    interpreter.define("false", Data::Bool(false));
+
+    interpreter.define_native("print", Arity::AtLeast(0), |_, args| {
+        print!("{}", join_lisp_strings(args));
+        Ok(Data::Nil)
+    });
+
+    interpreter.define_native("println", Arity::AtLeast(0), |_, args| {
+        println!("{}", join_lisp_strings(args));
+        Ok(Data::Nil)
+    });
+
+    interpreter.define_native("eval", Arity::Exactly(1), |interpreter, args| {
+        interpreter.eval(Ok(args[0].clone()))
+    });
+
+    interpreter.define_native("apply", Arity::AtLeast(2), |interpreter, args| {
+        // (apply f a b '(c d)) calls (f a b c d)
+        let (list, leading) = args[1..].split_last().unwrap();
+        let mut call_args = leading.to_vec();
+        match list.list_items() {
+            Some(items) => call_args.extend(items),
+            None => {
+                return Err(Error::new(
+                    ErrorKind::Type,
+                    format!("apply: last argument must be a list, got {}", list.repr()),
+                ))
+            }
+        }
+        interpreter.apply(&args[0], &call_args)
+    });
+
+    arithmetic::load(interpreter);
+    chars::load(interpreter);
+    exceptions::load(interpreter);
+    format::load(interpreter);
+    lists::load(interpreter);
+    predicates::load(interpreter);
+    strings::load(interpreter);
+    symbols::load(interpreter);
+    vectors::load(interpreter);
+}
+
+/// Joins the arguments the way `print` displays them.
+fn join_lisp_strings(args: &[Data]) -> String {
+    args.iter()
+        .map(Data::to_lisp_string)
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// An index argument. A negative one is out of range, and the error
+/// shows `args`, the arguments of the call.
+fn index(function: &str, data: &Data, args: &[Data]) -> Result<usize, Error> {
+    match data {
+        Data::Int(i) if *i >= 0 => Ok(*i as usize),
+        Data::Int(_) => Err(out_of_range(function, args)),
+        x => Err(wrong_type(function, x)),
+    }
+}
+
+/// The error for an index past the end of a list, string or vector,
+/// which shows the arguments of the call.
+fn out_of_range(function: &str, args: &[Data]) -> Error {
+    Error::new(
+        ErrorKind::Value,
+        format!("{}: index out of range:", function),
+    )
+    .with_irritants(args.to_vec())
+}
+
+/// Checks that `test` holds for every pair of adjacent arguments, which
+/// `convert` takes out of their `Data` (or rejects). For the comparison
+/// functions like `char<?`.
+fn compare_chain<T: Ord>(
+    function: &str,
+    args: &[Data],
+    convert: fn(&str, &Data) -> Result<T, Error>,
+    test: fn(Ordering) -> bool,
+) -> EvalResult {
+    let values = args
+        .iter()
+        .map(|arg| convert(function, arg))
+        .collect::<Result<Vec<T>, Error>>()?;
+
+    Ok(Data::Bool(
+        values.windows(2).all(|pair| test(pair[0].cmp(&pair[1]))),
+    ))
+}
+
+/// The error for a function that got an argument of the wrong type.
+fn wrong_type(function: &str, arg: &Data) -> Error {
+    Error::new(
+        ErrorKind::Type,
+        format!(
+            "attempted to use {} in function {} (wrong argument type)",
+            arg.repr(),
+            function
+        ),
+    )
+}
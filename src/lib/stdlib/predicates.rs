@@ -0,0 +1,55 @@
+//! Type predicates and other functions that work on any value.
+
+use crate::lib::data::{Arity, Data};
+use crate::lib::interpreter::Interpreter;
+
+pub fn load(interpreter: &mut Interpreter) {
+    interpreter.define_native("not", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(!args[0].is_truthy()))
+    });
+
+    interpreter.define_native("eq?", Arity::Exactly(2), |_, args| {
+        Ok(Data::Bool(args[0].is_eq(&args[1])))
+    });
+
+    interpreter.define_native("equal?", Arity::Exactly(2), |_, args| {
+        Ok(Data::Bool(args[0].is_equal(&args[1])))
+    });
+
+    interpreter.define_native("boolean?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(matches!(args[0], Data::Bool(_))))
+    });
+
+    interpreter.define_native("char?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(matches!(args[0], Data::Char(_))))
+    });
+
+    interpreter.define_native("string?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(matches!(args[0], Data::Str(_))))
+    });
+
+    interpreter.define_native("symbol?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(matches!(args[0], Data::Symbol(_))))
+    });
+
+    interpreter.define_native("procedure?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(matches!(
+            args[0],
+            Data::RustFunction(_) | Data::LispFunction(_)
+        )))
+    });
+
+    interpreter.define_native("null?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(args[0].is_null()))
+    });
+
+    // Only proper lists are lists: (a . b) is a pair but not a list, and
+    // the empty list is a list but not a pair
+    interpreter.define_native("list?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(args[0].is_list()))
+    });
+
+    interpreter.define_native("pair?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(args[0].car().is_some()))
+    });
+}
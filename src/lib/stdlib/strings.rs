@@ -0,0 +1,302 @@
+//! Functions on strings.
+//!
+//! Strings are indexed by characters (Unicode scalar values), not by
+//! bytes, so `(string-ref "héllo" 2)` is `#\l`.
+
+use super::{compare_chain, index, out_of_range, wrong_type};
+use crate::lib::data::{Arity, Data};
+use crate::lib::error::{Error, ErrorKind};
+use crate::lib::interpreter::Interpreter;
+use crate::lib::parser::parse_number_literal;
+use num_bigint::BigInt;
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+pub fn load(interpreter: &mut Interpreter) {
+    interpreter.define_native("string-length", Arity::Exactly(1), |_, args| {
+        Ok(Data::Int(
+            string("string-length", &args[0])?.chars().count() as i64,
+        ))
+    });
+
+    interpreter.define_native("string-ref", Arity::Exactly(2), |_, args| {
+        let s = string("string-ref", &args[0])?;
+        let k = index("string-ref", &args[1], args)?;
+        s.chars()
+            .nth(k)
+            .map(Data::Char)
+            .ok_or_else(|| out_of_range("string-ref", args))
+    });
+
+    // (substring s start end) is the part of s from the character at
+    // start up to the one at end, left out. end defaults to the end of s
+    interpreter.define_native("substring", Arity::Between(2, 3), |_, args| {
+        let s = string("substring", &args[0])?;
+        let start = index("substring", &args[1], args)?;
+        let start = byte_offset(&s, start).ok_or_else(|| out_of_range("substring", args))?;
+        let end = match args.get(2) {
+            Some(end) => byte_offset(&s, index("substring", end, args)?)
+                .filter(|end| *end >= start)
+                .ok_or_else(|| out_of_range("substring", args))?,
+            None => s.len(),
+        };
+        Ok(Data::Str(s[start..end].into()))
+    });
+
+    interpreter.define_native("string-append", Arity::AtLeast(0), |_, args| {
+        let mut result = String::new();
+        for arg in args {
+            result.push_str(&string("string-append", arg)?);
+        }
+        Ok(Data::Str(result.into()))
+    });
+
+    // (string-split s separator) splits s at every occurrence of the
+    // separator (a string or a character), and without one, at runs of
+    // whitespace
+    interpreter.define_native("string-split", Arity::Between(1, 2), |_, args| {
+        let s = string("string-split", &args[0])?;
+        let parts: Vec<&str> = match args.get(1) {
+            None => s.split_whitespace().collect(),
+            Some(Data::Char(c)) => s.split(*c).collect(),
+            Some(Data::Str(separator)) if separator.is_empty() => {
+                return Err(Error::new(
+                    ErrorKind::Value,
+                    "string-split: empty separator",
+                ))
+            }
+            Some(Data::Str(separator)) => s.split(&**separator).collect(),
+            Some(x) => return Err(wrong_type("string-split", x)),
+        };
+        Ok(Data::list(
+            parts
+                .into_iter()
+                .map(|part| Data::Str(part.into()))
+                .collect(),
+        ))
+    });
+
+    // (string-join list separator) joins a list of strings, putting the
+    // separator (a space by default) between them
+    interpreter.define_native("string-join", Arity::Between(1, 2), |_, args| {
+        let parts = args[0]
+            .list_items()
+            .ok_or_else(|| wrong_type("string-join", &args[0]))?
+            .iter()
+            .map(|part| string("string-join", part))
+            .collect::<Result<Vec<Rc<str>>, Error>>()?;
+        let separator = match args.get(1) {
+            Some(separator) => string("string-join", separator)?,
+            None => " ".into(),
+        };
+        Ok(Data::Str(parts.join(&separator).into()))
+    });
+
+    interpreter.define_native("string-trim", Arity::Exactly(1), |_, args| {
+        Ok(Data::Str(string("string-trim", &args[0])?.trim().into()))
+    });
+
+    interpreter.define_native("string-trim-left", Arity::Exactly(1), |_, args| {
+        Ok(Data::Str(
+            string("string-trim-left", &args[0])?.trim_start().into(),
+        ))
+    });
+
+    interpreter.define_native("string-trim-right", Arity::Exactly(1), |_, args| {
+        Ok(Data::Str(
+            string("string-trim-right", &args[0])?.trim_end().into(),
+        ))
+    });
+
+    // Unlike char-upcase, these can change the length of the string, as
+    // in (string-upcase "straße") => "STRASSE"
+    interpreter.define_native("string-upcase", Arity::Exactly(1), |_, args| {
+        Ok(Data::Str(
+            string("string-upcase", &args[0])?.to_uppercase().into(),
+        ))
+    });
+
+    interpreter.define_native("string-downcase", Arity::Exactly(1), |_, args| {
+        Ok(Data::Str(
+            string("string-downcase", &args[0])?.to_lowercase().into(),
+        ))
+    });
+
+    // (string-index s c) is the index of the first character of s that is
+    // c, or that c returns true for if it's a predicate, or #f
+    interpreter.define_native("string-index", Arity::Exactly(2), |interpreter, args| {
+        let s = string("string-index", &args[0])?;
+        for (i, c) in s.chars().enumerate() {
+            let found = match &args[1] {
+                Data::Char(wanted) => c == *wanted,
+                predicate => interpreter.apply(predicate, &[Data::Char(c)])?.is_truthy(),
+            };
+            if found {
+                return Ok(Data::Int(i as i64));
+            }
+        }
+        Ok(Data::Bool(false))
+    });
+
+    // (string-contains s part) is the index where part first appears in
+    // s, or #f
+    interpreter.define_native("string-contains", Arity::Exactly(2), |_, args| {
+        let s = string("string-contains", &args[0])?;
+        let part = string("string-contains", &args[1])?;
+        Ok(match s.find(&*part) {
+            Some(offset) => Data::Int(s[..offset].chars().count() as i64),
+            None => Data::Bool(false),
+        })
+    });
+
+    // (string-replace s from to) replaces every occurrence of from in s
+    interpreter.define_native("string-replace", Arity::Exactly(3), |_, args| {
+        let s = string("string-replace", &args[0])?;
+        let from = string("string-replace", &args[1])?;
+        let to = string("string-replace", &args[2])?;
+        if from.is_empty() {
+            return Err(Error::new(
+                ErrorKind::Value,
+                "string-replace: empty pattern",
+            ));
+        }
+        Ok(Data::Str(s.replace(&*from, &to).into()))
+    });
+
+    interpreter.define_native("string->list", Arity::Exactly(1), |_, args| {
+        Ok(Data::list(
+            string("string->list", &args[0])?
+                .chars()
+                .map(Data::Char)
+                .collect(),
+        ))
+    });
+
+    interpreter.define_native("list->string", Arity::Exactly(1), |_, args| {
+        let chars = args[0]
+            .list_items()
+            .ok_or_else(|| wrong_type("list->string", &args[0]))?;
+        let mut result = String::new();
+        for c in chars {
+            match c {
+                Data::Char(c) => result.push(c),
+                x => return Err(wrong_type("list->string", &x)),
+            }
+        }
+        Ok(Data::Str(result.into()))
+    });
+
+    // (string->number s radix) reads s as a number literal, in base radix
+    // (10 by default) unless it has a prefix like #x. Returns #f if s
+    // isn't a number
+    interpreter.define_native("string->number", Arity::Between(1, 2), |_, args| {
+        let s = string("string->number", &args[0])?;
+        let radix = radix("string->number", args.get(1))?;
+        let lower = s.to_ascii_lowercase();
+        let has_radix = ["#x", "#o", "#b", "#d"].iter().any(|p| lower.contains(p));
+        let text = match radix_prefix(radix) {
+            Some(prefix) if !has_radix => format!("{}{}", prefix, s),
+            _ => s.to_string(),
+        };
+        Ok(match parse_number_literal(&text) {
+            Some(number) => Data::from(number),
+            None => Data::Bool(false),
+        })
+    });
+
+    // (number->string n radix) writes n in base radix (10 by default).
+    // Only exact numbers can be written in other bases
+    interpreter.define_native("number->string", Arity::Between(1, 2), |_, args| {
+        let radix = radix("number->string", args.get(1))?;
+        let written = match &args[0] {
+            Data::Int(_) | Data::BigInt(_) | Data::Rational(_) | Data::Float(_) if radix == 10 => {
+                args[0].repr()
+            }
+            Data::Int(i) => BigInt::from(*i).to_str_radix(radix),
+            Data::BigInt(i) => i.to_str_radix(radix),
+            Data::Rational(r) => format!(
+                "{}/{}",
+                r.numer().to_str_radix(radix),
+                r.denom().to_str_radix(radix)
+            ),
+            Data::Float(_) => {
+                return Err(Error::new(
+                    ErrorKind::Value,
+                    "number->string: inexact numbers can only be written in base 10",
+                ))
+            }
+            x => return Err(wrong_type("number->string", x)),
+        };
+        Ok(Data::Str(written.into()))
+    });
+
+    interpreter.define_native("string=?", Arity::AtLeast(1), |_, args| {
+        compare_chain("string=?", args, string, |ordering| {
+            ordering == Ordering::Equal
+        })
+    });
+
+    interpreter.define_native("string<?", Arity::AtLeast(1), |_, args| {
+        compare_chain("string<?", args, string, |ordering| {
+            ordering == Ordering::Less
+        })
+    });
+
+    interpreter.define_native("string>?", Arity::AtLeast(1), |_, args| {
+        compare_chain("string>?", args, string, |ordering| {
+            ordering == Ordering::Greater
+        })
+    });
+
+    interpreter.define_native("string<=?", Arity::AtLeast(1), |_, args| {
+        compare_chain("string<=?", args, string, |ordering| {
+            ordering != Ordering::Greater
+        })
+    });
+
+    interpreter.define_native("string>=?", Arity::AtLeast(1), |_, args| {
+        compare_chain("string>=?", args, string, |ordering| {
+            ordering != Ordering::Less
+        })
+    });
+}
+
+fn string(function: &str, data: &Data) -> Result<Rc<str>, Error> {
+    match data {
+        Data::Str(s) => Ok(s.clone()),
+        x => Err(wrong_type(function, x)),
+    }
+}
+
+/// Where the character at `index` starts in `s`, which is the length of
+/// `s` for the index right after its last character.
+fn byte_offset(s: &str, index: usize) -> Option<usize> {
+    s.char_indices()
+        .map(|(offset, _)| offset)
+        .chain(std::iter::once(s.len()))
+        .nth(index)
+}
+
+/// The optional radix argument of a number conversion, which is one of
+/// the bases number literals can be written in.
+fn radix(function: &str, data: Option<&Data>) -> Result<u32, Error> {
+    match data {
+        None => Ok(10),
+        Some(Data::Int(radix @ (2 | 8 | 10 | 16))) => Ok(*radix as u32),
+        Some(x) => Err(Error::new(
+            ErrorKind::Value,
+            format!("{}: invalid radix {}", function, x.repr()),
+        )),
+    }
+}
+
+/// The prefix that gives a number literal the radix `radix`, if it isn't
+/// the default one.
+fn radix_prefix(radix: u32) -> Option<&'static str> {
+    match radix {
+        2 => Some("#b"),
+        8 => Some("#o"),
+        16 => Some("#x"),
+        _ => None,
+    }
+}
@@ -0,0 +1,32 @@
+//! Functions on symbols.
+
+use super::wrong_type;
+use crate::lib::data::{Arity, Data};
+use crate::lib::interpreter::Interpreter;
+use crate::lib::symbol::Symbol;
+
+pub fn load(interpreter: &mut Interpreter) {
+    interpreter.define_native("symbol->string", Arity::Exactly(1), |_, args| {
+        match &args[0] {
+            Data::Symbol(symbol) => Ok(Data::Str(symbol.name())),
+            x => Err(wrong_type("symbol->string", x)),
+        }
+    });
+
+    interpreter.define_native("string->symbol", Arity::Exactly(1), |_, args| {
+        match &args[0] {
+            Data::Str(s) => Ok(Data::Symbol(Symbol::intern(s))),
+            x => Err(wrong_type("string->symbol", x)),
+        }
+    });
+
+    // (gensym) or (gensym "prefix") makes a symbol that isn't eq? to any
+    // other, not even to one read or made with string->symbol
+    interpreter.define_native("gensym", Arity::Between(0, 1), |_, args| {
+        match args.first() {
+            None => Ok(Data::Symbol(Symbol::gensym("g"))),
+            Some(Data::Str(prefix)) => Ok(Data::Symbol(Symbol::gensym(prefix))),
+            Some(x) => Err(wrong_type("gensym", x)),
+        }
+    });
+}
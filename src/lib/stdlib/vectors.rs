@@ -0,0 +1,160 @@
+//! Functions on vectors.
+
+use super::{index, out_of_range, wrong_type};
+use crate::lib::data::{Arity, Data};
+use crate::lib::error::{Error, ErrorKind};
+use crate::lib::interpreter::Interpreter;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+pub fn load(interpreter: &mut Interpreter) {
+    interpreter.define_native("vector?", Arity::Exactly(1), |_, args| {
+        Ok(Data::Bool(matches!(args[0], Data::Vector(_))))
+    });
+
+    interpreter.define_native("vector", Arity::AtLeast(0), |_, args| {
+        Ok(Data::vector(args.to_vec()))
+    });
+
+    // (make-vector k fill) makes a vector of k items, all of them fill (or
+    // nil)
+    interpreter.define_native("make-vector", Arity::Between(1, 2), |_, args| {
+        let length = match &args[0] {
+            Data::Int(length) if *length >= 0 => *length as usize,
+            Data::Int(_) => {
+                return Err(
+                    Error::new(ErrorKind::Value, "make-vector: negative length:")
+                        .with_irritants(vec![args[0].clone()]),
+                )
+            }
+            x => return Err(wrong_type("make-vector", x)),
+        };
+        let fill = args.get(1).cloned().unwrap_or(Data::Nil);
+        // A length too big to allocate is an error instead of an abort
+        let mut items = Vec::new();
+        if items.try_reserve_exact(length).is_err() {
+            return Err(Error::new(
+                ErrorKind::Value,
+                "make-vector: not enough memory for a vector of length",
+            )
+            .with_irritants(vec![args[0].clone()]));
+        }
+        items.resize(length, fill);
+        Ok(Data::vector(items))
+    });
+
+    interpreter.define_native("vector-length", Arity::Exactly(1), |_, args| {
+        Ok(Data::Int(
+            vector("vector-length", &args[0])?.borrow().len() as i64
+        ))
+    });
+
+    interpreter.define_native("vector-ref", Arity::Exactly(2), |_, args| {
+        let vector = vector("vector-ref", &args[0])?;
+        let k = index("vector-ref", &args[1], args)?;
+        let item = vector.borrow().get(k).cloned();
+        item.ok_or_else(|| out_of_range("vector-ref", args))
+    });
+
+    interpreter.define_native("vector-set!", Arity::Exactly(3), |_, args| {
+        let vector = vector("vector-set!", &args[0])?;
+        let k = index("vector-set!", &args[1], args)?;
+        match vector.borrow_mut().get_mut(k) {
+            Some(item) => *item = args[2].clone(),
+            None => return Err(out_of_range("vector-set!", args)),
+        }
+        Ok(Data::Nil)
+    });
+
+    // Adds an item at the end of the vector, growing it
+    interpreter.define_native("vector-push!", Arity::Exactly(2), |_, args| {
+        vector("vector-push!", &args[0])?
+            .borrow_mut()
+            .push(args[1].clone());
+        Ok(Data::Nil)
+    });
+
+    interpreter.define_native("vector-fill!", Arity::Exactly(2), |_, args| {
+        for item in vector("vector-fill!", &args[0])?.borrow_mut().iter_mut() {
+            *item = args[1].clone();
+        }
+        Ok(Data::Nil)
+    });
+
+    // (vector-map f vector...) is like map, for vectors
+    interpreter.define_native("vector-map", Arity::AtLeast(2), |interpreter, args| {
+        let vectors = args[1..]
+            .iter()
+            .map(|arg| Ok(vector("vector-map", arg)?.borrow().clone()))
+            .collect::<Result<Vec<Vec<Data>>, Error>>()?;
+        let length = vectors.iter().map(Vec::len).min().unwrap_or(0);
+
+        let mut results = Vec::with_capacity(length);
+        for i in 0..length {
+            let call_args: Vec<Data> = vectors.iter().map(|v| v[i].clone()).collect();
+            results.push(interpreter.apply(&args[0], &call_args)?);
+        }
+        Ok(Data::vector(results))
+    });
+
+    interpreter.define_native("vector->list", Arity::Exactly(1), |_, args| {
+        Ok(Data::list(
+            vector("vector->list", &args[0])?.borrow().clone(),
+        ))
+    });
+
+    interpreter.define_native("list->vector", Arity::Exactly(1), |_, args| {
+        match args[0].list_items() {
+            Some(items) => Ok(Data::vector(items)),
+            None => Err(wrong_type("list->vector", &args[0])),
+        }
+    });
+}
+
+fn vector(function: &str, data: &Data) -> Result<Rc<RefCell<Vec<Data>>>, Error> {
+    match data {
+        Data::Vector(vector) => Ok(vector.clone()),
+        x => Err(wrong_type(function, x)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::symbol::Symbol;
+
+    /// Calls the vector function `name`.
+    fn call(name: &str, args: &[Data]) -> Result<Data, Error> {
+        let mut interpreter = Interpreter::new(Vec::new());
+        let function = interpreter.eval(Ok(Data::Symbol(Symbol::intern(name))))?;
+        interpreter.apply(&function, args)
+    }
+
+    #[test]
+    fn make_vector() {
+        let vector = call("make-vector", &[Data::Int(3), Data::Int(0)]).unwrap();
+        assert_eq!(vector.repr(), "#(0 0 0)");
+        let error = call("make-vector", &[Data::Int(-1)]).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Value);
+    }
+
+    #[test]
+    fn make_huge_vector() {
+        let error = call("make-vector", &[Data::Int(100_000_000_000_000)]).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Value);
+    }
+
+    #[test]
+    fn indices_out_of_range() {
+        let vector = Data::vector(vec![Data::Int(1), Data::Int(2)]);
+        for k in [-1, 2].iter() {
+            let error = call("vector-ref", &[vector.clone(), Data::Int(*k)]).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::Value, "{}", k);
+            let args = [vector.clone(), Data::Int(*k), Data::Int(0)];
+            let error = call("vector-set!", &args).unwrap_err();
+            assert_eq!(error.kind, ErrorKind::Value, "{}", k);
+        }
+        let error = call("vector-ref", &[vector, Data::Str("0".into())]).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::Type);
+    }
+}
@@ -0,0 +1,85 @@
+//! Symbols, interned so that comparing, hashing and copying them doesn't
+//! touch their names.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+thread_local! {
+    static TABLE: RefCell<SymbolTable> = RefCell::default();
+}
+
+/// The names of all symbols, indexed by their ids. Only interned symbols
+/// can be found by name.
+#[derive(Default)]
+struct SymbolTable {
+    names: Vec<Rc<str>>,
+    ids: HashMap<Rc<str>, Symbol>,
+}
+
+impl SymbolTable {
+    fn add(&mut self, name: Rc<str>) -> Symbol {
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(name);
+        symbol
+    }
+}
+
+/// A symbol, which is an id in the symbol table.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// The symbol named `name`, which is the same every time.
+    pub fn intern(name: &str) -> Symbol {
+        TABLE.with(|table| {
+            let mut table = table.borrow_mut();
+            if let Some(&symbol) = table.ids.get(name) {
+                return symbol;
+            }
+            let name: Rc<str> = name.into();
+            let symbol = table.add(name.clone());
+            table.ids.insert(name, symbol);
+            symbol
+        })
+    }
+
+    /// Makes a symbol that's different from every other one, even from the
+    /// ones with the same name. Its name is `prefix` followed by a number.
+    pub fn gensym(prefix: &str) -> Symbol {
+        TABLE.with(|table| {
+            let mut table = table.borrow_mut();
+            let name = format!("{}{}", prefix, table.names.len());
+            table.add(name.into())
+        })
+    }
+
+    /// The symbol's id, which is small enough to index tables of symbols
+    /// with.
+    pub fn id(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn name(self) -> Rc<str> {
+        TABLE.with(|table| table.borrow().names[self.0 as usize].clone())
+    }
+}
+
+impl PartialEq<str> for Symbol {
+    fn eq(&self, other: &str) -> bool {
+        TABLE.with(|table| &*table.borrow().names[self.0 as usize] == other)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
@@ -1,29 +1,191 @@
+// `src/lib` is a module of the binary, not a library target.
+#![allow(special_module_name)]
+
 extern crate pest;
 #[macro_use]
 extern crate pest_derive;
 
 pub mod lib;
 
+use lib::error::{Error, ErrorKind};
+use std::io::Read;
+
+const EXIT_SUCCESS: i32 = 0;
+const EXIT_RUNTIME_ERROR: i32 = 1;
+const EXIT_PARSE_ERROR: i32 = 2;
+
+const USAGE: &str = "\
+Usage: crisp [options] [script | -] [args...]
+
+Runs a crisp program. Without a script, the REPL is started.
+
+Options:
+  -e <expr>     evaluate <expr> instead of reading a script
+  -             read the program from the standard input
+  --check       parse the program without running it
+  -h, --help    show this message and exit";
+
+/// Where the program to be run comes from.
+enum Source {
+    File(String),
+    Stdin,
+    Expression(String),
+}
+
+impl Source {
+    /// The name errors refer to the source by.
+    fn name(&self) -> &str {
+        match self {
+            Source::File(path) => path,
+            Source::Stdin => "<stdin>",
+            Source::Expression(_) => "<expression>",
+        }
+    }
+}
+
+/// What was asked for on the command line.
+enum Command {
+    Repl,
+    Run {
+        source: Source,
+        check_only: bool,
+        args: Vec<String>,
+    },
+    Help,
+}
+
+/// The interpreter recurses on the Rust stack, so it runs in a thread with
+/// a stack big enough for its maximum recursion depth.
+const STACK_SIZE: usize = 512 * 1024 * 1024;
+
 fn main() {
-    let code = r#"(println "Hello, World!") (println "This should be on another line.") (println (+ 1 2))"#;
-    let exit_code = init_interpreter(code);
+    let exit_code = std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("could not start the interpreter thread")
+        .join()
+        .unwrap_or(EXIT_RUNTIME_ERROR);
+
     std::process::exit(exit_code);
 }
 
-fn init_interpreter(code: &str) -> i32 {
+fn run() -> i32 {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+
+    match parse_arguments(&args) {
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            EXIT_SUCCESS
+        }
+        Ok(Command::Repl) => run_repl(),
+        Ok(Command::Run {
+            source,
+            check_only,
+            args,
+        }) => match read_source(&source) {
+            Ok(code) => init_interpreter(source.name(), &code, check_only, args),
+            Err(e) => {
+                eprintln!("crisp: {}", e);
+                EXIT_PARSE_ERROR
+            }
+        },
+        Err(e) => {
+            eprintln!("crisp: {}\n\n{}", e, USAGE);
+            EXIT_PARSE_ERROR
+        }
+    }
+}
+
+/// Parses the command line (without the program name). Everything after
+/// the script name (or after `-e <expr>`) is passed on to the program.
+fn parse_arguments(args: &[String]) -> Result<Command, String> {
+    let mut check_only = false;
+    let mut source = None;
+    let mut rest = args.iter();
+
+    while let Some(arg) = rest.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "--check" => check_only = true,
+            "-e" => match rest.next() {
+                Some(expr) => {
+                    source = Some(Source::Expression(expr.clone()));
+                    break;
+                }
+                None => return Err("option -e requires an expression".into()),
+            },
+            "-" => {
+                source = Some(Source::Stdin);
+                break;
+            }
+            "--" => {
+                if let Some(path) = rest.next() {
+                    source = Some(Source::File(path.clone()));
+                }
+                break;
+            }
+            option if option.starts_with('-') => return Err(format!("unknown option: {}", option)),
+            path => {
+                source = Some(Source::File(path.into()));
+                break;
+            }
+        }
+    }
+
+    match source {
+        Some(source) => Ok(Command::Run {
+            source,
+            check_only,
+            args: rest.cloned().collect(),
+        }),
+        None if check_only => Err("--check needs a script, - or -e <expr>".into()),
+        None => Ok(Command::Repl),
+    }
+}
+
+fn read_source(source: &Source) -> Result<String, Error> {
+    match source {
+        Source::File(path) => std::fs::read_to_string(path)
+            .map_err(|e| Error::new(ErrorKind::Io, format!("could not read {}: {}", path, e))),
+        Source::Stdin => {
+            let mut code = String::new();
+            std::io::stdin().read_to_string(&mut code).map_err(|e| {
+                Error::new(
+                    ErrorKind::Io,
+                    format!("could not read the standard input: {}", e),
+                )
+            })?;
+            Ok(code)
+        }
+        Source::Expression(expr) => Ok(expr.clone()),
+    }
+}
+
+fn run_repl() -> i32 {
+    lib::repl::init();
+    EXIT_SUCCESS
+}
+
+fn init_interpreter(name: &str, code: &str, check_only: bool, args: Vec<String>) -> i32 {
     use lib::data::Data;
     use lib::interpreter::Interpreter;
-    println!("Code: {}", code);
 
-    match lib::parser::parse_program(code) {
+    match lib::parser::parse_program(&lib::span::Source::new(name, code)) {
+        Ok(_) if check_only => EXIT_SUCCESS,
         Ok(prog) => {
-            let mut interpreter =
-                Interpreter::new(prog.iter().map(|pre| Data::from(pre.clone())).collect());
-            interpreter.start()
+            let mut interpreter = Interpreter::new(prog);
+            interpreter.define(
+                "*args*",
+                Data::list(args.into_iter().map(|arg| Data::Str(arg.into())).collect()),
+            );
+            match interpreter.start() {
+                0 => EXIT_SUCCESS,
+                _ => EXIT_RUNTIME_ERROR,
+            }
         }
         Err(e) => {
-            println!("Parsing error:\n{}", e);
-            2
+            eprintln!("{}", e.render());
+            EXIT_PARSE_ERROR
         }
     }
 }